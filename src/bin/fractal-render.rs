@@ -0,0 +1,288 @@
+//! Renders one image of a fractal to a PNG or TIFF file without opening a
+//! window, in tiles if it is larger than the GPU allows, or the frames of a
+//! zoom animation.
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    if let Err(error) = cli::run() {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+// There is no headless device on the web.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod cli {
+    use fractal_egui_demo::{
+        fractal::{Mode, Scene},
+        gradient::GradientSpec,
+        headless::{write_image, AnimationFormat, AnimationWriter, Headless, ImageFormat},
+        params::{in_range, ColoringSettings, Params, MAX_SIZE, PARAMS_VERSION},
+        session::PaletteSettings,
+        view::{parse_real, View},
+    };
+    use std::path::{Path, PathBuf};
+
+    /// Side of the tiles: an image larger than that is rendered a strip of
+    /// tiles at a time.
+    const DEFAULT_TILE: u32 = 2048;
+
+    const USAGE: &str = "\
+Usage: fractal-render [OPTIONS] OUTPUT.png|OUTPUT.tiff
+       fractal-render [OPTIONS] --frames N [ANIMATION OPTIONS] OUTPUT.png|OUTPUT.gif|FRAME{}.png
+
+Options:
+  --params FILE            parameter file (.toml or .json) setting all of the
+                           image, which the other options override
+  --mode NAME              mandelbrot, burning-ship, tricorn, celtic or
+                           perpendicular, or any of them followed by -julia
+                           [default: mandelbrot]
+  --center RE,IM           center of the view [default: the home view]
+  --zoom ZOOM              the view is 2^-ZOOM tall [default: the home view]
+  --c RE,IM                constant of julia [default: 0,0]
+  --max-iterations N       [default: 1024]
+  --gradient NAME          preset gradient [default: cubehelix_default]
+  --size WIDTHxHEIGHT      [default: 1024x1024]
+  --tile N                 render in tiles of at most NxN pixels
+                           [default: 2048, or the GPU limit if lower]
+  --fallback               render on the fallback (software) adapter
+  --help
+
+Animation options, zooming from the view above to the target view:
+  --frames N               number of frames, written as an animated PNG or GIF,
+                           or numbered in place of {} in the output path
+  --to-center RE,IM        center of the target view [default: the center]
+  --to-zoom ZOOM           zoom of the target view [default: the zoom]
+  --delay MS               time each frame is shown [default: 40]";
+
+    /// The command line, parsed.
+    struct Options {
+        mode: Mode,
+        /// The view of the parameter file, else the home view.
+        view: Option<View>,
+        center: Option<[String; 2]>,
+        zoom: Option<f64>,
+        c: [f32; 2],
+        max_iterations: u32,
+        escape_radius: f32,
+        palette: PaletteSettings,
+        coloring: ColoringSettings,
+        size: [u32; 2],
+        tile: u32,
+        fallback: bool,
+        frames: Option<u32>,
+        to_center: Option<[String; 2]>,
+        to_zoom: Option<f64>,
+        delay: u16,
+        output: PathBuf,
+    }
+
+    impl Options {
+        fn new() -> Options {
+            Options {
+                mode: Mode::Mandelbrot,
+                view: None,
+                center: None,
+                zoom: None,
+                c: [0.0, 0.0],
+                max_iterations: 1024,
+                escape_radius: 2.0,
+                palette: PaletteSettings::default(),
+                coloring: ColoringSettings::default(),
+                size: [1024, 1024],
+                tile: DEFAULT_TILE,
+                fallback: false,
+                frames: None,
+                to_center: None,
+                to_zoom: None,
+                delay: 40,
+                output: PathBuf::new(),
+            }
+        }
+
+        fn from_params(params: Params) -> Options {
+            Options {
+                mode: params.mode,
+                view: Some(params.view),
+                c: params.c,
+                max_iterations: params.max_iterations,
+                escape_radius: params.escape_radius,
+                palette: params.palette,
+                coloring: params.coloring,
+                size: params.size,
+                ..Options::new()
+            }
+        }
+
+        fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+            let args: Vec<String> = args.collect();
+            // The parameter file comes first, wherever it is given.
+            let mut options = match args.iter().position(|arg| arg == "--params") {
+                Some(i) => {
+                    let path = args.get(i + 1).ok_or("--params needs a value")?;
+                    Options::from_params(Params::read(Path::new(path))?)
+                }
+                None => Options::new(),
+            };
+            let mut output = None;
+            let mut args = args.into_iter();
+            while let Some(arg) = args.next() {
+                let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+                match arg.as_str() {
+                    "--params" => {
+                        value()?;
+                    }
+                    "--mode" => {
+                        let name = value()?;
+                        options.mode =
+                            Mode::from_name(&name).ok_or(format!("unknown mode {name}"))?;
+                    }
+                    "--center" => {
+                        let center = value()?;
+                        options.center = Some(pair(&center, ',')?.map(str::to_owned));
+                    }
+                    "--zoom" => options.zoom = Some(number(&value()?)?),
+                    "--c" => options.c = numbers(&value()?, ',')?,
+                    "--max-iterations" => options.max_iterations = number(&value()?)?,
+                    "--gradient" => options.palette.gradient = GradientSpec::Preset(value()?),
+                    "--size" => options.size = numbers(&value()?, 'x')?,
+                    "--tile" => options.tile = number(&value()?)?,
+                    "--fallback" => options.fallback = true,
+                    "--frames" => options.frames = Some(number(&value()?)?),
+                    "--to-center" => {
+                        let center = value()?;
+                        options.to_center = Some(pair(&center, ',')?.map(str::to_owned));
+                    }
+                    "--to-zoom" => options.to_zoom = Some(number(&value()?)?),
+                    "--delay" => options.delay = number(&value()?)?,
+                    "--help" | "-h" => return Err(USAGE.to_owned()),
+                    _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+                    _ if output.is_none() => output = Some(PathBuf::from(arg)),
+                    _ => return Err(format!("unexpected argument {arg}")),
+                }
+            }
+            options.output = output.ok_or("missing the output file")?;
+            Ok(options)
+        }
+
+        fn scene(&self) -> Result<Scene, String> {
+            // Before the view, which divides by it.
+            for size in self.size {
+                in_range("size", size, 1..=MAX_SIZE)?;
+            }
+            let formula = self.mode.formula();
+            let aspect = f64::from(self.size[0]) / f64::from(self.size[1]);
+            let view = self
+                .view
+                .clone()
+                .unwrap_or_else(|| View::home(formula, aspect));
+            let params = Params {
+                version: PARAMS_VERSION,
+                mode: self.mode,
+                size: self.size,
+                c: self.c,
+                max_iterations: self.max_iterations,
+                escape_radius: self.escape_radius,
+                view: self.view(view, &self.center, self.zoom)?,
+                palette: self.palette.clone(),
+                coloring: self.coloring.clone(),
+            };
+            params.validate()?;
+            params.scene()
+        }
+
+        /// `view` moved to `center` and `zoom`, where given.
+        fn view(
+            &self,
+            mut view: View,
+            center: &Option<[String; 2]>,
+            zoom: Option<f64>,
+        ) -> Result<View, String> {
+            if let Some(zoom) = zoom {
+                view.zoom = zoom;
+            }
+            // Before the center, which takes about `zoom` bits.
+            view.validate()?;
+            if let Some(center) = center {
+                let bits = view.bits(self.size);
+                for (i, text) in center.iter().enumerate() {
+                    view.center[i] =
+                        parse_real(text, bits).ok_or(format!("invalid coordinate {text}"))?;
+                }
+            }
+            Ok(view)
+        }
+    }
+
+    /// Splits `a<separator>b`.
+    fn pair(text: &str, separator: char) -> Result<[&str; 2], String> {
+        let (a, b) = text.split_once(separator).ok_or(format!(
+            "expected two values separated by {separator}: {text}"
+        ))?;
+        Ok([a.trim(), b.trim()])
+    }
+
+    fn number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+        text.parse().map_err(|_| format!("invalid number {text}"))
+    }
+
+    fn numbers<T: std::str::FromStr>(text: &str, separator: char) -> Result<[T; 2], String> {
+        let [a, b] = pair(text, separator)?;
+        Ok([number(a)?, number(b)?])
+    }
+
+    pub fn run() -> Result<(), String> {
+        let options = Options::parse(std::env::args().skip(1))?;
+        if let Some(frames) = options.frames {
+            return animate(&options, frames);
+        }
+        let format = ImageFormat::from_path(&options.output).ok_or(format!(
+            "unknown image format of {}, expected .png or .tiff",
+            options.output.display()
+        ))?;
+        let scene = options.scene()?;
+        let headless = open(&options)?;
+        let tile = options.tile.clamp(1, headless.max_size());
+        write_image(&options.output, format, options.size, tile, |write| {
+            headless.render_tiled(&scene, options.size, tile, write)
+        })
+    }
+
+    /// The device to render on, telling which it is and what it cannot do.
+    fn open(options: &Options) -> Result<Headless, String> {
+        let headless = Headless::new(options.fallback)?;
+        let info = headless.adapter_info();
+        eprintln!("rendering on {} ({:?})", info.name, info.backend);
+        let limit = headless.max_iterations();
+        if options.max_iterations > limit {
+            eprintln!(
+                "this adapter holds at most {limit} iterations exactly, rendering with {limit}"
+            );
+        }
+        Ok(headless)
+    }
+
+    fn animate(options: &Options, frames: u32) -> Result<(), String> {
+        if frames == 0 {
+            return Err("--frames must be at least 1".to_owned());
+        }
+        let format = AnimationFormat::from_path(&options.output).ok_or(format!(
+            "unknown animation format of {}, expected .png, .gif or {{}} in the path",
+            options.output.display()
+        ))?;
+        let scene = options.scene()?;
+        let target = options.view(scene.view.clone(), &options.to_center, options.to_zoom)?;
+        let headless = open(options)?;
+        let mut writer =
+            AnimationWriter::new(&options.output, format, options.size, frames, options.delay)?;
+        headless.render_animation(&scene, &target, frames, options.size, |frame, rgba| {
+            eprint!("\rframe {}/{frames}", frame + 1);
+            writer.write_frame(frame, rgba)
+        })?;
+        eprintln!();
+        writer.finish()
+    }
+}
@@ -0,0 +1,182 @@
+use crate::fractal::{Mode, MAX_ITERATIONS};
+use crate::params::{finite, in_range};
+use crate::session::PaletteSettings;
+use crate::view::View;
+use serde::{Deserialize, Serialize};
+
+/// Key of the bookmarks in the eframe storage.
+pub const BOOKMARKS_KEY: &str = "bookmarks";
+
+/// Version of `BookmarkCollection` written by this build, see
+/// `SESSION_VERSION`.
+pub const BOOKMARKS_VERSION: u32 = 1;
+
+/// A named location: everything needed to get back to it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub mode: Mode,
+    pub view: View,
+    pub c: [f32; 2],
+    pub max_iterations: u32,
+    pub palette: PaletteSettings,
+}
+
+impl Bookmark {
+    /// Checks the values like `Params::validate()`, as bookmarks are
+    /// imported from anywhere.
+    pub fn validate(&self) -> Result<(), String> {
+        self.view.validate()?;
+        self.c.into_iter().try_for_each(|v| finite("c", v))?;
+        in_range("max_iterations", self.max_iterations, 1..=MAX_ITERATIONS)?;
+        self.palette.validate()
+    }
+}
+
+/// Bookmarks as they are stored and shared, in JSON.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BookmarkCollection {
+    pub version: u32,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// Just the version of a `BookmarkCollection`, to pick how to read the rest.
+#[derive(Deserialize)]
+struct CollectionVersion {
+    version: u32,
+}
+
+impl BookmarkCollection {
+    pub fn new(bookmarks: Vec<Bookmark>) -> BookmarkCollection {
+        BookmarkCollection {
+            version: BOOKMARKS_VERSION,
+            bookmarks,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("bookmarks serialize")
+    }
+
+    /// Parses a collection, rejecting it if any of its bookmarks is invalid.
+    pub fn from_json(json: &str) -> Result<BookmarkCollection, String> {
+        let collection = BookmarkCollection::parse(json)?;
+        for bookmark in &collection.bookmarks {
+            bookmark
+                .validate()
+                .map_err(|error| format!("invalid bookmark {}: {error}", bookmark.name))?;
+        }
+        Ok(collection)
+    }
+
+    fn parse(json: &str) -> Result<BookmarkCollection, String> {
+        let version = serde_json::from_str::<CollectionVersion>(json)
+            .map_err(|error| format!("not a bookmark collection: {error}"))?
+            .version;
+        match version {
+            BOOKMARKS_VERSION => serde_json::from_str(json)
+                .map_err(|error| format!("invalid bookmark collection: {error}")),
+            _ => Err(format!("unknown bookmark collection version {version}")),
+        }
+    }
+
+    /// The stored bookmarks, or none if there are none this build can read.
+    /// Invalid bookmarks are dropped, keeping the others.
+    pub fn load(storage: &dyn eframe::Storage) -> BookmarkCollection {
+        let json = storage.get_string(BOOKMARKS_KEY);
+        match json.as_deref().map(BookmarkCollection::parse) {
+            Some(Ok(mut collection)) => {
+                collection
+                    .bookmarks
+                    .retain(|bookmark| match bookmark.validate() {
+                        Ok(()) => true,
+                        Err(error) => {
+                            log::warn!("ignoring the stored bookmark {}: {error}", bookmark.name);
+                            false
+                        }
+                    });
+                collection
+            }
+            Some(Err(error)) => {
+                log::warn!("ignoring the stored bookmarks: {error}");
+                BookmarkCollection::new(Vec::new())
+            }
+            None => BookmarkCollection::new(Vec::new()),
+        }
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        storage.set_string(BOOKMARKS_KEY, self.to_json());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view::parse_real;
+    use std::collections::HashMap;
+
+    fn bookmark(name: &str) -> Bookmark {
+        Bookmark {
+            name: name.to_owned(),
+            mode: Mode::Julia,
+            view: View {
+                center: ["-0.75", "0.125"].map(|v| parse_real(v, 0).unwrap()),
+                zoom: 3.5,
+            },
+            c: [-0.8, 0.156],
+            max_iterations: 2000,
+            palette: PaletteSettings::default(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let collection = BookmarkCollection::new(vec![bookmark("a"), bookmark("b")]);
+        assert_eq!(
+            BookmarkCollection::from_json(&collection.to_json()),
+            Ok(collection)
+        );
+    }
+
+    /// Storage holding strings in memory.
+    #[derive(Default)]
+    struct Memory(HashMap<String, String>);
+
+    impl eframe::Storage for Memory {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_owned(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        let invalid: [fn(&mut Bookmark); 4] = [
+            |b| b.view.zoom = 1e9,
+            |b| b.c[0] = f32::NAN,
+            |b| b.max_iterations = u32::MAX,
+            |b| b.palette.len = 0,
+        ];
+        for (i, invalidate) in invalid.into_iter().enumerate() {
+            let mut bookmark = bookmark("bad");
+            invalidate(&mut bookmark);
+            assert!(bookmark.validate().is_err(), "{i}");
+        }
+
+        let mut deep = bookmark("deep");
+        deep.view.zoom = 1e9;
+        let collection = BookmarkCollection::new(vec![bookmark("fine"), deep]);
+        let error = BookmarkCollection::from_json(&collection.to_json()).unwrap_err();
+        assert!(error.contains("deep"), "{error}");
+        let mut storage = Memory::default();
+        collection.save(&mut storage);
+        let loaded = BookmarkCollection::load(&storage);
+        assert_eq!(loaded.bookmarks, [bookmark("fine")]);
+    }
+}
@@ -0,0 +1,972 @@
+use crate::wgsl_struct::ColoringParams;
+use eframe::wgpu;
+use serde::{Deserialize, Serialize};
+
+/// Format of the texture written by the iteration pass: iterations, smooth
+/// iteration count, distance estimate and trap distance of each pixel, see
+/// `coloring.wgsl`.
+pub const DATA_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+/// `DATA_FORMAT` if `adapter` can render to it, or else a half-float format,
+/// which some downlevel (GL) adapters only render to, see `max_iterations()`.
+pub fn data_format(adapter: &wgpu::Adapter) -> wgpu::TextureFormat {
+    let features = adapter.get_texture_format_features(DATA_FORMAT);
+    match features
+        .allowed_usages
+        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    {
+        true => DATA_FORMAT,
+        false => wgpu::TextureFormat::Rgba16Float,
+    }
+}
+
+/// Largest iteration count of the half-float data format: f16 holds every
+/// integer up to 2048, but not all of those above.
+pub const HALF_MAX_ITERATIONS: u32 = 2048;
+
+/// The largest `max_iterations` whose counts a data texture of `data_format`
+/// holds exactly.
+pub fn max_iterations(data_format: wgpu::TextureFormat) -> u32 {
+    match data_format {
+        DATA_FORMAT => u32::MAX,
+        _ => HALF_MAX_ITERATIONS,
+    }
+}
+
+/// What the data texture holds outside of the domain of the formula.
+pub const OUTSIDE: [f32; 4] = [-1.0, 0.0, 0.0, 0.0];
+
+/// Width of the palette and distribution textures, longer ones wrap onto more
+/// rows so that their length is not limited by `max_texture_dimension_2d`.
+const ROW_LEN: usize = 256;
+
+/// Largest width of the darkened exterior along the boundary, in pixels.
+pub const MAX_BOUNDARY: f32 = 8.0;
+
+/// Smallest distance colored by `Coloring::Distance` and `Coloring::Trap`.
+const MIN_DISTANCE: f32 = 1e-20;
+
+/// Workgroup size of `cs_count` in `histogram.wgsl`, in each dimension.
+const COUNT_WORKGROUP: u32 = 16;
+
+/// Number of colors sampled from the interior gradient, a single row of its
+/// texture.
+pub const INTERIOR_LEN: usize = ROW_LEN;
+
+/// Spreads consecutive periods far apart on the interior gradient.
+const PERIOD_STEP: f32 = 0.618034;
+
+/// How escaped points are mapped onto the palette.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Coloring {
+    /// The iteration count.
+    Banded = 0,
+    /// The normalized iteration count.
+    Smooth = 1,
+    /// The fraction of escaped pixels in the view with at most as many
+    /// iterations, spread once over the whole palette.
+    Histogram = 2,
+    /// The exterior distance estimate in pixels, on a log scale.
+    Distance = 3,
+    /// The distance from the orbit to the orbit trap, see `Trap`.
+    Trap = 4,
+}
+
+impl Coloring {
+    pub const ALL: [Coloring; 5] = [
+        Coloring::Banded,
+        Coloring::Smooth,
+        Coloring::Histogram,
+        Coloring::Distance,
+        Coloring::Trap,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Coloring::Banded => "banded",
+            Coloring::Smooth => "smooth",
+            Coloring::Histogram => "histogram",
+            Coloring::Distance => "distance",
+            Coloring::Trap => "trap",
+        }
+    }
+}
+
+/// How points that did not escape are colored, from the interior gradient.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Interior {
+    Black = 0,
+    /// The period of the attracting cycle the orbit fell into, which tells
+    /// the hyperbolic components of the Mandelbrot set apart.
+    Period = 1,
+    /// |z| after the last iteration.
+    Modulus = 2,
+    /// The magnitude of the multiplier of the attracting cycle, 0 at the
+    /// centers of the hyperbolic components and 1 on their boundary.
+    Multiplier = 3,
+}
+
+impl Interior {
+    pub const ALL: [Interior; 4] = [
+        Interior::Black,
+        Interior::Period,
+        Interior::Modulus,
+        Interior::Multiplier,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Interior::Black => "black",
+            Interior::Period => "period",
+            Interior::Modulus => "|z|",
+            Interior::Multiplier => "multiplier",
+        }
+    }
+}
+
+/// How the palette is read between two entries.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Filter {
+    /// The entry below.
+    Nearest = 0,
+    /// Linear interpolation between the entries around.
+    Linear = 1,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 2] = [Filter::Nearest, Filter::Linear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Nearest => "nearest",
+            Filter::Linear => "linear",
+        }
+    }
+}
+
+/// A cyclic palette of any length and how iteration counts are mapped onto
+/// it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Palette {
+    pub colors: Vec<[f32; 4]>,
+    /// Shift of the first entry, as a fraction of the palette length.
+    pub offset: f32,
+    /// Palette entries per iteration; the palette repeats every
+    /// `colors.len() / density` iterations.
+    pub density: f32,
+    pub reverse: bool,
+    pub filter: Filter,
+}
+
+impl Palette {
+    /// One entry per iteration, starting at the first one.
+    pub fn new(colors: Vec<[f32; 4]>) -> Palette {
+        assert!(!colors.is_empty(), "a palette needs at least one color");
+        Palette {
+            colors,
+            offset: 0.0,
+            density: 1.0,
+            reverse: false,
+            filter: Filter::Nearest,
+        }
+    }
+
+    /// The color of `iterations`, the CPU counterpart of `palette_color()` in
+    /// `coloring.wgsl`.
+    pub fn color(&self, iterations: f32) -> [f32; 4] {
+        let len = self.colors.len() as f32;
+        self.sample(iterations * self.density + self.offset * len)
+    }
+
+    /// The color at `fraction` of the way from the first entry to the last,
+    /// the CPU counterpart of `palette_fraction()`.
+    pub fn fraction_color(&self, fraction: f32) -> [f32; 4] {
+        let len = self.colors.len() as f32;
+        self.sample(fraction * (len - 1.0) + self.offset * len)
+    }
+
+    /// The color `position` entries into the palette, see `palette_sample()`.
+    fn sample(&self, position: f32) -> [f32; 4] {
+        let len = self.colors.len();
+        let position = self.wrap(position);
+        let index = (position.floor() as usize).min(len - 1);
+        let lower = self.colors[index];
+        if self.filter == Filter::Nearest {
+            return lower;
+        }
+        let upper = self.colors[(index + 1) % len];
+        let t = position - position.floor();
+        [0, 1, 2, 3].map(|i| lower[i] + (upper[i] - lower[i]) * t)
+    }
+
+    /// `position` reversed if needed and wrapped to `[0, len)`.
+    fn wrap(&self, mut position: f32) -> f32 {
+        let len = self.colors.len() as f32;
+        if self.reverse {
+            position = len - 1.0 - position;
+        }
+        position - (position / len).floor() * len
+    }
+
+    /// The parameters of `coloring.wgsl`, darkening the exterior within
+    /// `boundary` pixels of the set; `stalk_width` is `Trap::stalk_width()`.
+    pub fn params(&self, coloring: Coloring, boundary: f32, stalk_width: f32) -> ColoringParams {
+        ColoringParams {
+            coloring: coloring as u32,
+            palette_len: self.colors.len() as u32,
+            palette_filter: self.filter as u32,
+            reverse: self.reverse as u32,
+            offset: self.offset,
+            density: self.density,
+            boundary,
+            stalk_width,
+            interior: Interior::Black as u32,
+            padding0: 0,
+            padding1: 0,
+            padding2: 0,
+        }
+    }
+}
+
+/// The palette position of `Coloring::Distance` for a distance estimate in
+/// pixels, or of `Coloring::Trap` for a trap distance.
+pub fn distance_position(distance: f32) -> f32 {
+    distance.max(MIN_DISTANCE).log2()
+}
+
+/// The color of `Coloring::Trap` for an escaped `texel`, see `trap_color()`
+/// in `coloring.wgsl`.
+pub fn trap_color(palette: &Palette, stalk_width: f32, texel: &[f32; 4]) -> [f32; 4] {
+    if stalk_width <= 0.0 {
+        palette.color(distance_position(texel[3]))
+    } else if texel[3] < stalk_width {
+        palette.fraction_color(texel[3] / stalk_width)
+    } else {
+        palette.color(texel[1])
+    }
+}
+
+/// How much of its color an escaped pixel at `distance` pixels from the set
+/// keeps, darkening the exterior within `boundary` pixels of it.
+pub fn boundary_shade(distance: f32, boundary: f32) -> f32 {
+    if boundary > 0.0 {
+        (distance / boundary).clamp(0.0, 1.0)
+    } else {
+        1.0
+    }
+}
+
+/// The color of a `texel` that did not escape, the CPU counterpart of
+/// `interior_color()` in `coloring.wgsl`.
+pub fn interior_color(interior: Interior, colors: &[[f32; 4]], texel: &[f32; 4]) -> [f32; 4] {
+    let fraction = match interior {
+        Interior::Modulus => Some((texel[2] / 2.0).clamp(0.0, 1.0)),
+        // The period and the multiplier are only known for attracting cycles.
+        _ if texel[1] == 0.0 => None,
+        Interior::Period => Some(((texel[1] - 1.0) * PERIOD_STEP).fract()),
+        Interior::Multiplier => Some(texel[3].clamp(0.0, 1.0)),
+        Interior::Black => None,
+    };
+    let Some(fraction) = fraction else {
+        return [0.0, 0.0, 0.0, 1.0];
+    };
+    let last = colors.len() - 1;
+    let position = fraction * last as f32;
+    let index = (position as usize).min(last);
+    let lower = colors[index];
+    let upper = colors[(index + 1).min(last)];
+    let t = position.fract();
+    [0, 1, 2, 3].map(|i| lower[i] + (upper[i] - lower[i]) * t)
+}
+
+/// Fraction of the escaped pixels of `data` with at most as many iterations,
+/// per iteration count up to `max_iterations`: the CPU counterpart of
+/// `histogram.wgsl`.
+pub fn cdf(data: &[[f32; 4]], max_iterations: u32) -> Vec<f32> {
+    let mut bins = vec![0; max_iterations as usize + 1];
+    count(data, &mut bins);
+    distribution(&bins)
+}
+
+/// Adds the escaped pixels of `data` to `bins`, one per iteration count, the
+/// last one taking the pixels past it too.
+pub fn count(data: &[[f32; 4]], bins: &mut [u64]) {
+    let last = bins.len() - 1;
+    for texel in data.iter().filter(|texel| texel[0] >= 1.0) {
+        bins[(texel[0] as usize).min(last)] += 1;
+    }
+}
+
+/// Fraction of the counts of `bins` in each bin or before it.
+pub fn distribution(bins: &[u64]) -> Vec<f32> {
+    let total = bins.iter().sum::<u64>().max(1) as f32;
+    bins.iter()
+        .scan(0, |cumulative, count| {
+            *cumulative += count;
+            Some(*cumulative as f32 / total)
+        })
+        .collect()
+}
+
+/// Where an escaped `texel` falls in the distribution `cdf`, interpolated by
+/// the smooth iteration count, see `fs_color()`.
+pub fn equalize(cdf: &[f32], texel: &[f32; 4]) -> f32 {
+    let iterations = (texel[0] as usize).clamp(1, cdf.len() - 1);
+    let t = (texel[1] - (iterations - 1) as f32).clamp(0.0, 1.0);
+    cdf[iterations - 1] + (cdf[iterations] - cdf[iterations - 1]) * t
+}
+
+/// The pass of `coloring.wgsl`, turning the data texture of the iteration
+/// pass into colors.
+pub struct ColoringPass {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    data: wgpu::TextureView,
+    palette_texture: wgpu::Texture,
+    /// The colors currently in `palette_texture`.
+    colors: Vec<[f32; 4]>,
+    interior_texture: wgpu::Texture,
+    /// The colors currently in `interior_texture`.
+    interior_colors: Vec<[f32; 4]>,
+    /// The distribution of `Coloring::Histogram`, one texel per iteration
+    /// count.
+    cdf_texture: wgpu::Texture,
+    /// `None` when the device has no compute shaders.
+    histogram: Option<HistogramPass>,
+}
+
+impl ColoringPass {
+    pub fn new(
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        data: &wgpu::TextureView,
+    ) -> ColoringPass {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("egui_plot_coloring_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("coloring.wgsl").into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("egui_plot_coloring_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1, wgpu::ShaderStages::FRAGMENT),
+                texture_entry(2, wgpu::ShaderStages::FRAGMENT),
+                texture_entry(3, wgpu::ShaderStages::FRAGMENT),
+                texture_entry(4, wgpu::ShaderStages::FRAGMENT),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("egui_plot_coloring_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("egui_plot_coloring_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_fullscreen"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_color"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: crate::fractal::MSAA_SAMPLE_COUNT,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        let params_buffer = <wgpu::Device as wgpu::util::DeviceExt>::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("egui_plot_coloring_params"),
+                contents: bytemuck::cast_slice(&[Palette::new(vec![[0.0; 4]]).params(
+                    Coloring::Banded,
+                    0.0,
+                    0.0,
+                )]),
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            },
+        );
+        let palette_texture = create_row_texture(
+            device,
+            "egui_plot_palette",
+            wgpu::TextureFormat::Rgba32Float,
+            palette_size(1),
+        );
+        let interior_texture = create_row_texture(
+            device,
+            "egui_plot_interior",
+            wgpu::TextureFormat::Rgba32Float,
+            palette_size(INTERIOR_LEN),
+        );
+        let cdf_texture = create_row_texture(
+            device,
+            "egui_plot_cdf",
+            wgpu::TextureFormat::R32Float,
+            cdf_size(1),
+        );
+        let limits = device.limits();
+        let histogram = (limits.max_compute_invocations_per_workgroup
+            >= COUNT_WORKGROUP * COUNT_WORKGROUP
+            && limits.max_storage_buffers_per_shader_stage >= 2)
+            .then(|| HistogramPass::new(device, data, 1));
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &params_buffer,
+            data,
+            [&palette_texture, &cdf_texture, &interior_texture],
+        );
+
+        ColoringPass {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            params_buffer,
+            data: data.clone(),
+            palette_texture,
+            colors: Vec::new(),
+            interior_texture,
+            interior_colors: Vec::new(),
+            cdf_texture,
+            histogram,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        params_buffer: &wgpu::Buffer,
+        data: &wgpu::TextureView,
+        [palette_texture, cdf_texture, interior_texture]: [&wgpu::Texture; 3],
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("egui_plot_coloring_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(data),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(
+                        &palette_texture.create_view(&Default::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(
+                        &cdf_texture.create_view(&Default::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(
+                        &interior_texture.create_view(&Default::default()),
+                    ),
+                },
+            ],
+        })
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.params_buffer,
+            &self.data,
+            [
+                &self.palette_texture,
+                &self.cdf_texture,
+                &self.interior_texture,
+            ],
+        );
+        if let Some(histogram) = &mut self.histogram {
+            histogram.rebind(device, &self.data);
+        }
+    }
+
+    /// Points the pass at a new data texture, after a resize.
+    pub fn set_data(&mut self, device: &wgpu::Device, data: &wgpu::TextureView) {
+        self.data = data.clone();
+        self.rebind(device);
+    }
+
+    /// Whether `Coloring::Histogram` is available on this device.
+    pub fn supports_histogram(&self) -> bool {
+        self.histogram.is_some()
+    }
+
+    /// Writes the parameters, and the colors of `palette` if they changed.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        params: &ColoringParams,
+        palette: &Palette,
+        interior: &[[f32; 4]],
+        max_iterations: u32,
+    ) {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[*params]));
+
+        // One bin per iteration count, from 0 to `max_iterations`.
+        let bins = max_iterations as usize + 1;
+        if self.cdf_texture.size() != cdf_size(bins) {
+            self.cdf_texture = create_row_texture(
+                device,
+                "egui_plot_cdf",
+                wgpu::TextureFormat::R32Float,
+                cdf_size(bins),
+            );
+            if let Some(histogram) = &mut self.histogram {
+                histogram.set_bins(device, bins);
+            }
+            self.rebind(device);
+        }
+
+        let mut resized = false;
+        if self.colors != palette.colors {
+            resized |= upload_colors(
+                device,
+                queue,
+                &mut self.palette_texture,
+                "egui_plot_palette",
+                &palette.colors,
+            );
+            self.colors = palette.colors.clone();
+        }
+        if self.interior_colors != interior {
+            // `interior_sample()` only reads the first row.
+            debug_assert!(interior.len() <= ROW_LEN);
+            resized |= upload_colors(
+                device,
+                queue,
+                &mut self.interior_texture,
+                "egui_plot_interior",
+                interior,
+            );
+            self.interior_colors = interior.to_vec();
+        }
+        if resized {
+            self.rebind(device);
+        }
+    }
+
+    /// Records the passes building the distribution of the data texture for
+    /// `Coloring::Histogram`.
+    pub fn equalize(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(histogram) = &self.histogram else {
+            return;
+        };
+        encoder.clear_buffer(&histogram.bins, 0, None);
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("egui_plot_histogram"),
+                timestamp_writes: None,
+            });
+            let size = self.data.texture().size();
+            cpass.set_bind_group(0, &histogram.bind_group, &[]);
+            cpass.set_pipeline(&histogram.count_pipeline);
+            cpass.dispatch_workgroups(
+                size.width.div_ceil(COUNT_WORKGROUP),
+                size.height.div_ceil(COUNT_WORKGROUP),
+                1,
+            );
+            cpass.set_pipeline(&histogram.scan_pipeline);
+            cpass.dispatch_workgroups(1, 1, 1);
+        }
+        let size = self.cdf_texture.size();
+        encoder.copy_buffer_to_texture(
+            wgpu::TexelCopyBufferInfo {
+                buffer: &histogram.cdf,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size.width * std::mem::size_of::<f32>() as u32),
+                    rows_per_image: None,
+                },
+            },
+            self.cdf_texture.as_image_copy(),
+            size,
+        );
+    }
+
+    /// Sets the distribution of `Coloring::Histogram`, one value per
+    /// iteration count, instead of building it with `equalize()`.
+    pub fn set_cdf(&self, queue: &wgpu::Queue, cdf: &[f32]) {
+        let size = self.cdf_texture.size();
+        let mut texels = cdf.to_vec();
+        texels.resize((size.width * size.height) as usize, 1.0);
+        queue.write_texture(
+            self.cdf_texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width * std::mem::size_of::<f32>() as u32),
+                rows_per_image: None,
+            },
+            size,
+        );
+    }
+
+    pub fn render_onto_renderpass<'rp>(&'rp self, rpass: &mut wgpu::RenderPass<'rp>) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+/// The compute pipelines of `histogram.wgsl` and their buffers.
+struct HistogramPass {
+    count_pipeline: wgpu::ComputePipeline,
+    scan_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    bins: wgpu::Buffer,
+    /// Holds whole rows of the distribution texture, to be copied into it.
+    cdf: wgpu::Buffer,
+}
+
+impl HistogramPass {
+    fn new(device: &wgpu::Device, data: &wgpu::TextureView, bins: usize) -> HistogramPass {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("egui_plot_histogram_shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("histogram.wgsl").into()),
+        });
+
+        let buffer_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("egui_plot_histogram_bind_group_layout"),
+            entries: &[
+                texture_entry(0, wgpu::ShaderStages::COMPUTE),
+                buffer_entry(1),
+                buffer_entry(2),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("egui_plot_histogram_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("egui_plot_histogram_pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let count_pipeline = create_pipeline("cs_count");
+        let scan_pipeline = create_pipeline("cs_scan");
+
+        let (bins, cdf) = Self::create_buffers(device, bins);
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, data, &bins, &cdf);
+
+        HistogramPass {
+            count_pipeline,
+            scan_pipeline,
+            bind_group_layout,
+            bind_group,
+            bins,
+            cdf,
+        }
+    }
+
+    /// The buffers of `bins` bins and of the distribution.
+    fn create_buffers(device: &wgpu::Device, bins: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+        let create_buffer = |label, len: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (len * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let size = cdf_size(bins);
+        (
+            // `cs_scan` takes the number of bins from the length of the buffer.
+            create_buffer(
+                "egui_plot_histogram_bins",
+                bins,
+                wgpu::BufferUsages::COPY_DST,
+            ),
+            create_buffer(
+                "egui_plot_histogram_cdf",
+                (size.width * size.height) as usize,
+                wgpu::BufferUsages::COPY_SRC,
+            ),
+        )
+    }
+
+    /// Replaces the buffers for `bins` bins, `rebind()` must follow.
+    fn set_bins(&mut self, device: &wgpu::Device, bins: usize) {
+        (self.bins, self.cdf) = Self::create_buffers(device, bins);
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        data: &wgpu::TextureView,
+        bins: &wgpu::Buffer,
+        cdf: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("egui_plot_histogram_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(data),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: bins.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cdf.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device, data: &wgpu::TextureView) {
+        self.bind_group =
+            Self::create_bind_group(device, &self.bind_group_layout, data, &self.bins, &self.cdf);
+    }
+}
+
+fn texture_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn create_row_texture(
+    device: &wgpu::Device,
+    label: &str,
+    format: wgpu::TextureFormat,
+    size: wgpu::Extent3d,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: Default::default(),
+    })
+}
+
+/// Writes `colors` to `texture`, first replacing it with one of the right size
+/// if needed. Returns whether it was replaced, which needs a rebind.
+fn upload_colors(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &mut wgpu::Texture,
+    label: &str,
+    colors: &[[f32; 4]],
+) -> bool {
+    let size = palette_size(colors.len());
+    let resized = texture.size() != size;
+    if resized {
+        *texture = create_row_texture(device, label, wgpu::TextureFormat::Rgba32Float, size);
+    }
+    // The last row is padded to the full width.
+    let mut texels = colors.to_vec();
+    texels.resize((size.width * size.height) as usize, [0.0; 4]);
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&texels),
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(size.width * std::mem::size_of::<[f32; 4]>() as u32),
+            rows_per_image: None,
+        },
+        size,
+    );
+    resized
+}
+
+/// Size of the palette texture holding `len` colors.
+fn palette_size(len: usize) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width: len.min(ROW_LEN) as u32,
+        height: len.div_ceil(ROW_LEN) as u32,
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Size of the distribution texture for `bins` iteration counts. Its rows are
+/// always full, as buffer copies need aligned rows.
+fn cdf_size(bins: usize) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width: ROW_LEN as u32,
+        height: bins.div_ceil(ROW_LEN) as u32,
+        depth_or_array_layers: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: [u32; 2] = [64, 48];
+    const MAX_ITERATIONS: u32 = 1000;
+
+    /// Texels escaping after counts spread over all of the bins and past the
+    /// last one, with some that did not escape or are outside of the domain.
+    fn data() -> Vec<[f32; 4]> {
+        (0..SIZE[0] * SIZE[1])
+            .map(|i| match i % 7 {
+                0 => OUTSIDE,
+                1 => [0.0; 4],
+                _ => [(i * 7919 % (MAX_ITERATIONS + 50)) as f32, 0.0, 0.0, 0.0],
+            })
+            .collect()
+    }
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default())).ok()?;
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))
+        .ok()
+    }
+
+    #[test]
+    fn cdf_is_a_distribution() {
+        let distribution = cdf(&data(), MAX_ITERATIONS);
+        assert_eq!(distribution.len(), MAX_ITERATIONS as usize + 1);
+        assert_eq!(distribution[0], 0.0);
+        assert!(distribution.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(distribution[MAX_ITERATIONS as usize], 1.0);
+        assert!(cdf(&[OUTSIDE, [0.0; 4]], 4).iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn cdf_matches_histogram_shader() {
+        let Some((device, queue)) = device() else {
+            eprintln!("no wgpu adapter, skipping");
+            return;
+        };
+        let data = data();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: SIZE[0],
+                height: SIZE[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DATA_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&data),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(SIZE[0] * std::mem::size_of::<[f32; 4]>() as u32),
+                rows_per_image: None,
+            },
+            texture.size(),
+        );
+        let view = texture.create_view(&Default::default());
+        let mut pass = ColoringPass::new(&device, wgpu::TextureFormat::Rgba8Unorm, &view);
+        if !pass.supports_histogram() {
+            eprintln!("no compute shaders, skipping");
+            return;
+        }
+        let palette = Palette::new(vec![[0.0; 4]]);
+        let params = palette.params(Coloring::Histogram, 0.0, 0.0);
+        pass.prepare(
+            &device,
+            &queue,
+            &params,
+            &palette,
+            &[[0.0; 4]],
+            MAX_ITERATIONS,
+        );
+        let cdf_buffer = &pass.histogram.as_ref().unwrap().cdf;
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: cdf_buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        pass.equalize(&mut encoder);
+        encoder.copy_buffer_to_buffer(cdf_buffer, 0, &readback, 0, cdf_buffer.size());
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+        let bytes = slice.get_mapped_range();
+        let gpu: &[f32] = bytemuck::cast_slice(&bytes);
+        let cpu = cdf(&data, MAX_ITERATIONS);
+        assert_eq!(&gpu[..cpu.len()], &cpu[..]);
+    }
+}
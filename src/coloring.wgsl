@@ -0,0 +1,190 @@
+// The coloring pass: maps the per-pixel output of the iteration pass onto the
+// palette, so that recoloring does not run the escape-time loop again.
+
+const COLORING_BANDED: u32 = 0u;
+const COLORING_SMOOTH: u32 = 1u;
+const COLORING_HISTOGRAM: u32 = 2u;
+const COLORING_DISTANCE: u32 = 3u;
+const COLORING_TRAP: u32 = 4u;
+
+// Smallest distance colored by `COLORING_DISTANCE` and `COLORING_TRAP`.
+const MIN_DISTANCE: f32 = 1e-20;
+
+const FILTER_NEAREST: u32 = 0u;
+
+const INTERIOR_PERIOD: u32 = 1u;
+const INTERIOR_MODULUS: u32 = 2u;
+const INTERIOR_MULTIPLIER: u32 = 3u;
+
+// Spreads consecutive periods far apart on the interior gradient.
+const PERIOD_STEP: f32 = 0.618034;
+
+struct ColoringParams {
+    coloring: u32,
+    palette_len: u32,
+    palette_filter: u32,
+    reverse: u32,
+    offset: f32,
+    density: f32,
+    boundary: f32,
+    stalk_width: f32,
+    interior: u32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: ColoringParams;
+
+// (iterations, smooth iteration count, distance estimate in pixels) of the
+// first point outside of the escape radius and the trap distance of the
+// orbit. Points that did not escape have 0 iterations followed by the period
+// of their attracting cycle (0 if none was found), the final |z| and the
+// magnitude of the multiplier of the cycle. Iterations are -1 outside of the
+// domain of the formula.
+@group(0) @binding(1)
+var data: texture_2d<f32>;
+
+// The colors of the palette, row by row.
+@group(0) @binding(2)
+var palette: texture_2d<f32>;
+
+// The cumulative distribution of the iteration counts of the escaped pixels,
+// row by row, see `histogram.wgsl`.
+@group(0) @binding(3)
+var cdf: texture_2d<f32>;
+
+// The colors of the interior gradient, in a single row.
+@group(0) @binding(4)
+var interior_palette: texture_2d<f32>;
+
+// One triangle covering the whole target.
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_color(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = textureLoad(data, vec2<i32>(position.xy), 0);
+    if (texel.x < 0.0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+    if (texel.x == 0.0) {
+        return interior_color(texel);
+    }
+    let color = escaped_color(texel);
+    // Darken the exterior within `boundary` pixels of the set.
+    if (params.boundary > 0.0) {
+        return vec4<f32>(color.rgb * clamp(texel.z / params.boundary, 0.0, 1.0), color.a);
+    }
+    return color;
+}
+
+fn escaped_color(texel: vec4<f32>) -> vec4<f32> {
+    if (params.coloring == COLORING_SMOOTH) {
+        return palette_color(texel.y);
+    }
+    if (params.coloring == COLORING_HISTOGRAM) {
+        return palette_fraction(equalize(texel));
+    }
+    if (params.coloring == COLORING_DISTANCE) {
+        return palette_color(log2(max(texel.z, MIN_DISTANCE)));
+    }
+    if (params.coloring == COLORING_TRAP) {
+        return trap_color(texel);
+    }
+    return palette_color(texel.x);
+}
+
+// Stalks spread over the whole palette and leave the smooth coloring around
+// them, other traps are colored on a log scale.
+fn trap_color(texel: vec4<f32>) -> vec4<f32> {
+    if (params.stalk_width > 0.0) {
+        if (texel.w < params.stalk_width) {
+            return palette_fraction(texel.w / params.stalk_width);
+        }
+        return palette_color(texel.y);
+    }
+    return palette_color(log2(max(texel.w, MIN_DISTANCE)));
+}
+
+// See `interior_fraction()` in `coloring.rs`.
+fn interior_color(texel: vec4<f32>) -> vec4<f32> {
+    let black = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    if (params.interior == INTERIOR_MODULUS) {
+        return interior_sample(clamp(texel.z / 2.0, 0.0, 1.0));
+    }
+    // The period and the multiplier are only known for attracting cycles.
+    if (texel.y == 0.0) {
+        return black;
+    }
+    if (params.interior == INTERIOR_PERIOD) {
+        return interior_sample(fract((texel.y - 1.0) * PERIOD_STEP));
+    }
+    if (params.interior == INTERIOR_MULTIPLIER) {
+        return interior_sample(clamp(texel.w, 0.0, 1.0));
+    }
+    return black;
+}
+
+// The interior gradient at `fraction` of the way from its first color to its
+// last.
+fn interior_sample(fraction: f32) -> vec4<f32> {
+    let len = textureDimensions(interior_palette).x;
+    let position = fraction * f32(len - 1u);
+    let index = min(u32(position), len - 1u);
+    let lower = textureLoad(interior_palette, vec2<u32>(index, 0u), 0);
+    let upper = textureLoad(interior_palette, vec2<u32>(min(index + 1u, len - 1u), 0u), 0);
+    return mix(lower, upper, fract(position));
+}
+
+fn cdf_entry(index: u32) -> f32 {
+    let width = textureDimensions(cdf).x;
+    return textureLoad(cdf, vec2<u32>(index % width, index / width), 0).x;
+}
+
+// Where an escaped texel falls in the distribution, interpolated by the smooth
+// iteration count, see `equalize()` in `coloring.rs`.
+fn equalize(texel: vec4<f32>) -> f32 {
+    let iterations = max(u32(texel.x), 1u);
+    let lower = cdf_entry(iterations - 1u);
+    let upper = cdf_entry(iterations);
+    return mix(lower, upper, clamp(texel.y - f32(iterations - 1u), 0.0, 1.0));
+}
+
+fn palette_entry(index: u32) -> vec4<f32> {
+    let width = textureDimensions(palette).x;
+    return textureLoad(palette, vec2<u32>(index % width, index / width), 0);
+}
+
+// The color of `iterations`, see `Palette::color()`.
+fn palette_color(iterations: f32) -> vec4<f32> {
+    let len = f32(params.palette_len);
+    return palette_sample(iterations * params.density + params.offset * len);
+}
+
+// The color at `fraction` of the way from the first entry to the last.
+fn palette_fraction(fraction: f32) -> vec4<f32> {
+    let len = f32(params.palette_len);
+    return palette_sample(fraction * (len - 1.0) + params.offset * len);
+}
+
+// The color `position` entries into the palette.
+fn palette_sample(position_in: f32) -> vec4<f32> {
+    let len = f32(params.palette_len);
+    var position = position_in;
+    if (params.reverse != 0u) {
+        position = len - 1.0 - position;
+    }
+    position = position - floor(position / len) * len;
+    let index = min(u32(position), params.palette_len - 1u);
+    let lower = palette_entry(index);
+    if (params.palette_filter == FILTER_NEAREST) {
+        return lower;
+    }
+    let upper = palette_entry((index + 1u) % params.palette_len);
+    return mix(lower, upper, fract(position));
+}
@@ -0,0 +1,451 @@
+use crate::coloring::{
+    boundary_shade, cdf, distance_position, equalize, interior_color, trap_color, Coloring,
+    Interior, Palette, OUTSIDE,
+};
+use crate::fractal::{Mode, Orbit, Variant};
+use crate::trap::Trap;
+use crate::view::View;
+use eframe::egui::{self, Color32, ColorImage, TextureHandle, TextureOptions};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// Everything the CPU renderer needs to iterate one image.
+///
+/// Mirrors the inputs of the GPU iteration pass: pixels outside of the
+/// formula's domain hold `OUTSIDE`, like the cleared data texture.
+#[derive(Clone, PartialEq)]
+pub struct CpuParams {
+    pub mode: Mode,
+    pub view: View,
+    pub size: [usize; 2],
+    pub max_iterations: u32,
+    pub c: [f64; 2],
+    pub escape_radius: f64,
+    pub trap: Trap,
+}
+
+/// How the iterated image is mapped onto colors, the inputs of the coloring
+/// pass.
+#[derive(Clone, PartialEq)]
+pub struct CpuColoring {
+    pub coloring: Coloring,
+    pub palette: Palette,
+    pub boundary: f32,
+    pub interior: Interior,
+    pub interior_colors: Vec<[f32; 4]>,
+}
+
+/// See `PERIOD_TOLERANCE` in the shader.
+const PERIOD_TOLERANCE: f64 = 1e-6;
+
+/// Iterations run per frame at most on the web, where the image is rendered
+/// on the UI thread a few pixels at a time, counting `max_iterations` for
+/// every pixel.
+#[cfg(target_arch = "wasm32")]
+const WEB_ITERATIONS_PER_FRAME: u64 = 4_000_000;
+
+/// Escape data of every pixel, in the layout of the GPU data texture.
+type Data = Vec<[f32; 4]>;
+
+/// Multithreaded f64 escape-time renderer producing the same palette-mapped
+/// image as the shaders, used as a reference for the GPU output.
+///
+/// Rendering runs on a background thread, or between frames on the web; a
+/// newer request cancels the one in flight and the last finished image stays
+/// on screen until it is replaced.
+/// Recoloring reuses the finished escape data and does not iterate again.
+pub struct CpuRenderer {
+    texture: Option<TextureHandle>,
+    rendered: Option<(CpuParams, Data)>,
+    colored: Option<CpuColoring>,
+    requested: Option<CpuParams>,
+    generation: Arc<AtomicU64>,
+    sender: Sender<(u64, CpuParams, Data)>,
+    receiver: Receiver<(u64, CpuParams, Data)>,
+    #[cfg(target_arch = "wasm32")]
+    progress: Option<Progress>,
+}
+
+/// An image being rendered on the web: the pixels before `next` are done.
+#[cfg(target_arch = "wasm32")]
+struct Progress {
+    generation: u64,
+    params: CpuParams,
+    data: Data,
+    next: usize,
+}
+
+impl Default for CpuRenderer {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        CpuRenderer {
+            texture: None,
+            rendered: None,
+            colored: None,
+            requested: None,
+            generation: Arc::new(AtomicU64::new(0)),
+            sender,
+            receiver,
+            #[cfg(target_arch = "wasm32")]
+            progress: None,
+        }
+    }
+}
+
+impl CpuRenderer {
+    /// Starts rendering `params` unless it is already rendered or in flight.
+    pub fn request(&mut self, ctx: &egui::Context, params: CpuParams) {
+        if self.requested.as_ref() == Some(&params) {
+            return;
+        }
+        self.requested = Some(params.clone());
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let current = Arc::clone(&self.generation);
+            let sender = self.sender.clone();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                if let Some(data) = render(&params, &current, generation) {
+                    if sender.send((generation, params, data)).is_ok() {
+                        ctx.request_repaint();
+                    }
+                }
+            });
+        }
+
+        // No threads on the web: `image()` renders a slice of the image each
+        // frame.
+        #[cfg(target_arch = "wasm32")]
+        {
+            let [width, height] = params.size;
+            self.progress = Some(Progress {
+                generation,
+                params,
+                data: vec![OUTSIDE; width * height],
+                next: 0,
+            });
+            ctx.request_repaint();
+        }
+    }
+
+    /// Renders the next pixels of the image in progress, sending it once it
+    /// is done and asking for another frame until then.
+    #[cfg(target_arch = "wasm32")]
+    fn advance(&mut self, ctx: &egui::Context) {
+        let Some(progress) = &mut self.progress else {
+            return;
+        };
+        let width = progress.params.size[0];
+        let budget = WEB_ITERATIONS_PER_FRAME / u64::from(progress.params.max_iterations.max(1));
+        let end = (progress.next + budget.max(1) as usize).min(progress.data.len());
+        while progress.next < end {
+            let [x, y] = [progress.next % width, progress.next / width];
+            let row_end = end.min((y + 1) * width);
+            render_row(
+                &progress.params,
+                y,
+                x,
+                &mut progress.data[progress.next..row_end],
+            );
+            progress.next = row_end;
+        }
+        if progress.next < progress.data.len() {
+            ctx.request_repaint();
+        } else if let Some(Progress {
+            generation,
+            params,
+            data,
+            ..
+        }) = self.progress.take()
+        {
+            let _ = self.sender.send((generation, params, data));
+        }
+    }
+
+    /// Colors the most recent finished image with `coloring` if either
+    /// changed, and returns the texture along with the view it was rendered
+    /// for.
+    pub fn image(
+        &mut self,
+        ctx: &egui::Context,
+        coloring: &CpuColoring,
+    ) -> Option<(&TextureHandle, &View)> {
+        #[cfg(target_arch = "wasm32")]
+        self.advance(ctx);
+        let latest = self
+            .receiver
+            .try_iter()
+            .filter(|(generation, _, _)| *generation == self.generation.load(Ordering::SeqCst))
+            .last();
+        if let Some((_, params, data)) = latest {
+            self.rendered = Some((params, data));
+            self.colored = None;
+        }
+        let (params, data) = self.rendered.as_ref()?;
+        if self.colored.as_ref() != Some(coloring) {
+            let cdf = match coloring.coloring {
+                Coloring::Histogram => cdf(data, params.max_iterations),
+                _ => Vec::new(),
+            };
+            let stalk_width = params.trap.stalk_width();
+            let image = ColorImage::new(
+                params.size,
+                data.iter()
+                    .map(|texel| color_texel(coloring, &cdf, stalk_width, texel))
+                    .collect(),
+            );
+            match &mut self.texture {
+                Some(texture) => texture.set(image, TextureOptions::LINEAR),
+                None => {
+                    self.texture =
+                        Some(ctx.load_texture("cpu_fractal", image, TextureOptions::LINEAR))
+                }
+            }
+            self.colored = Some(coloring.clone());
+        }
+        Some((self.texture.as_ref()?, &params.view))
+    }
+}
+
+/// Renders the whole image, returning `None` if a newer request superseded
+/// `generation` before it finished.
+#[cfg(not(target_arch = "wasm32"))]
+fn render(params: &CpuParams, current: &AtomicU64, generation: u64) -> Option<Data> {
+    let [width, height] = params.size;
+    let mut pixels = vec![OUTSIDE; width * height];
+    if width == 0 || height == 0 {
+        return Some(pixels);
+    }
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    // Interleave rows between threads so that expensive regions (the interior
+    // of the set) are spread evenly.
+    let mut buckets: Vec<Vec<_>> = (0..threads).map(|_| Vec::new()).collect();
+    for (y, row) in pixels.chunks_mut(width).enumerate() {
+        buckets[y % threads].push((y, row));
+    }
+
+    let render_bucket = |bucket: Vec<(usize, &mut [[f32; 4]])>| {
+        for (y, row) in bucket {
+            if current.load(Ordering::Relaxed) != generation {
+                return;
+            }
+            render_row(params, y, 0, row);
+        }
+    };
+    std::thread::scope(|scope| {
+        for bucket in buckets {
+            scope.spawn(move || render_bucket(bucket));
+        }
+    });
+
+    (current.load(Ordering::SeqCst) == generation).then_some(pixels)
+}
+
+/// Renders the pixels of row `y` from column `x_start` on into `row`.
+fn render_row(params: &CpuParams, y: usize, x_start: usize, row: &mut [[f32; 4]]) {
+    let [width, height] = params.size;
+    let bounds = params.view.bounds([width as u32, height as u32]);
+    let [x_min, y_min] = bounds.min();
+    let [x_max, y_max] = bounds.max();
+    let formula = params.mode.formula();
+    let [[domain_x_min, domain_y_min], [domain_x_max, domain_y_max]] =
+        formula.domain.map(|corner| corner.map(f64::from));
+    // Row 0 is the top of the image, which is the maximum of the y range.
+    let im = y_max - (y as f64 + 0.5) / height as f64 * (y_max - y_min);
+    for (x, pixel) in (x_start..).zip(row.iter_mut()) {
+        let re = x_min + (x as f64 + 0.5) / width as f64 * (x_max - x_min);
+        if re < domain_x_min || re > domain_x_max || im < domain_y_min || im > domain_y_max {
+            continue;
+        }
+        let orbit = formula.start([re, im], params.c);
+        let radius_sqr = params.escape_radius * params.escape_radius;
+        let pixel_size = (y_max - y_min) / height as f64;
+        // The same texel as the shader's `escape_data()`.
+        let escape = escape_time(
+            orbit,
+            formula.variant,
+            params.max_iterations,
+            radius_sqr,
+            &params.trap,
+        );
+        *pixel = match escape.escaped {
+            None => [
+                0.0,
+                f64::from(escape.period),
+                escape.z[0].hypot(escape.z[1]),
+                escape.multiplier,
+            ]
+            .map(|v| v as f32),
+            Some((iterations, z, dz)) => {
+                let norm_sqr = z[0] * z[0] + z[1] * z[1];
+                let overshoot = norm_sqr.ln() / radius_sqr.ln();
+                let smooth = f64::from(iterations) - overshoot.log2();
+                // The exterior distance estimate |z| ln|z| / |dz| in pixels,
+                // see `distance_estimate()` in the shader.
+                let dz_norm = dz[0].hypot(dz[1]) * pixel_size;
+                let distance = if dz_norm.is_finite() {
+                    norm_sqr.sqrt() * 0.5 * norm_sqr.ln() / dz_norm
+                } else {
+                    0.0
+                };
+                [f64::from(iterations), smooth, distance, escape.trap].map(|v| v as f32)
+            }
+        };
+    }
+}
+
+/// The fate of one orbit, the CPU counterpart of the shader's `Escape`.
+struct Escape {
+    /// The number of iterations until `z` escaped, counted the same way as
+    /// `fs_main`, and `z` and its derivative at that point.
+    escaped: Option<(u32, [f64; 2], [f64; 2])>,
+    /// The smallest distance from the orbit to the trap.
+    trap: f64,
+    /// The last point of orbits that did not escape, and the period and
+    /// multiplier magnitude of the attracting cycle they fell into, with a
+    /// period of 0 if none was found.
+    z: [f64; 2],
+    period: u32,
+    multiplier: f64,
+}
+
+/// Brent's cycle detection, the CPU counterpart of `periodicity_step()`.
+struct Periodicity {
+    reference: [f64; 2],
+    reference_index: u32,
+    log_derivative: f64,
+    matched: bool,
+    period: u32,
+    multiplier: f64,
+}
+
+impl Periodicity {
+    fn new() -> Periodicity {
+        Periodicity {
+            reference: [0.0, 0.0],
+            reference_index: 0,
+            log_derivative: 0.0,
+            matched: true,
+            period: 0,
+            multiplier: 0.0,
+        }
+    }
+
+    /// Looks for a cycle at `z`, the point of the orbit after `i` iterations.
+    fn step(&mut self, i: u32, z: [f64; 2]) {
+        let offset = [z[0] - self.reference[0], z[1] - self.reference[1]];
+        if !self.matched && offset[0].hypot(offset[1]) < PERIOD_TOLERANCE {
+            self.matched = true;
+            self.period = i - self.reference_index;
+            self.multiplier = self.log_derivative.exp();
+        }
+        if i.is_power_of_two() || i == 0 {
+            self.reference = z;
+            self.reference_index = i;
+            self.log_derivative = 0.0;
+            self.matched = false;
+        }
+        self.log_derivative += (2.0 * z[0].hypot(z[1])).ln();
+    }
+}
+
+fn escape_time(
+    orbit: Orbit,
+    variant: Variant,
+    max_iterations: u32,
+    radius_sqr: f64,
+    trap: &Trap,
+) -> Escape {
+    let Orbit {
+        mut z,
+        c,
+        mut dz,
+        dc,
+    } = orbit;
+    // See `TRAP_FAR` in the shader.
+    let mut trap_distance = 1e30_f64;
+    let mut periodicity = Periodicity::new();
+    for i in 0..max_iterations {
+        // The starting point is not part of the orbit.
+        if i > 0 {
+            trap_distance = trap_distance.min(trap.distance(z));
+        }
+        if z[0] * z[0] + z[1] * z[1] > radius_sqr {
+            return Escape {
+                escaped: Some((i + 1, z, dz)),
+                trap: trap_distance,
+                z,
+                period: 0,
+                multiplier: 0.0,
+            };
+        }
+        periodicity.step(i, z);
+        dz = variant.derivative(z, dz, dc);
+        z = variant.iter(z, c);
+    }
+    Escape {
+        escaped: None,
+        trap: trap_distance,
+        z,
+        period: periodicity.period,
+        multiplier: periodicity.multiplier,
+    }
+}
+
+/// The orbit `z_0, z_1, …` of `point` of the plane of `mode`, as iterated by
+/// `escape_time()`: at most `len` points, ending with the first one past
+/// `escape_radius`.
+pub fn orbit(
+    mode: Mode,
+    point: [f64; 2],
+    c: [f64; 2],
+    escape_radius: f64,
+    len: usize,
+) -> Vec<[f64; 2]> {
+    let formula = mode.formula();
+    let Orbit { mut z, c, .. } = formula.start(point, c);
+    let mut orbit = Vec::with_capacity(len);
+    while orbit.len() < len {
+        orbit.push(z);
+        if z[0] * z[0] + z[1] * z[1] > escape_radius * escape_radius {
+            break;
+        }
+        z = formula.variant.iter(z, c);
+    }
+    orbit
+}
+
+/// The CPU counterpart of `fs_color()` in `coloring.wgsl`. `cdf` is only
+/// read by `Coloring::Histogram` and `stalk_width` by `Coloring::Trap`.
+fn color_texel(coloring: &CpuColoring, cdf: &[f32], stalk_width: f32, texel: &[f32; 4]) -> Color32 {
+    if texel[0] < 0.0 {
+        Color32::TRANSPARENT
+    } else if texel[0] == 0.0 {
+        to_color32(interior_color(
+            coloring.interior,
+            &coloring.interior_colors,
+            texel,
+        ))
+    } else {
+        let palette = &coloring.palette;
+        let [r, g, b, a] = match coloring.coloring {
+            Coloring::Banded => palette.color(texel[0]),
+            Coloring::Smooth => palette.color(texel[1]),
+            Coloring::Histogram => palette.fraction_color(equalize(cdf, texel)),
+            Coloring::Distance => palette.color(distance_position(texel[2])),
+            Coloring::Trap => trap_color(palette, stalk_width, texel),
+        };
+        let shade = boundary_shade(texel[2], coloring.boundary);
+        to_color32([r * shade, g * shade, b * shade, a])
+    }
+}
+
+fn to_color32([r, g, b, a]: [f32; 4]) -> Color32 {
+    let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color32::from_rgba_premultiplied(to_u8(r), to_u8(g), to_u8(b), to_u8(a))
+}
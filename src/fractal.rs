@@ -0,0 +1,1304 @@
+use crate::coloring::{self, Coloring, ColoringPass, Interior, Palette, OUTSIDE};
+use crate::perturbation::{PerturbationView, Real, RealExp, ReferenceOrbit};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::readback;
+use crate::readback::{Texel, TexelProbe};
+use crate::trap::Trap;
+use crate::view::View;
+use crate::wgsl_struct::{ColoringParams, PerturbationParams, UniformParams, Vertex};
+use eframe::egui::PaintCallbackInfo;
+use eframe::egui_wgpu::{CallbackResources, ScreenDescriptor};
+use eframe::wgpu::{CommandBuffer, CommandEncoder, Device, Queue, RenderPass};
+use eframe::{
+    egui::{self /*, plot::PlotBounds*/},
+    egui_wgpu, wgpu,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use wgpu::StoreOp::Store;
+
+pub const MSAA_SAMPLE_COUNT: u32 = 1;
+const VERTEX_NUM: usize = 6;
+
+const DEFAULT_WIDTH: u32 = 1;
+const DEFAULT_HEIGHT: u32 = 1;
+
+/// A few ulps of df64, whose mantissa has about 48 bits.
+const DF64_EPSILON: f64 = 1.0 / (1u64 << 44) as f64;
+
+/// The fractal families that can be plotted.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Mode {
+    Mandelbrot,
+    Julia,
+    BurningShip,
+    BurningShipJulia,
+    Tricorn,
+    TricornJulia,
+    Celtic,
+    CelticJulia,
+    Perpendicular,
+    PerpendicularJulia,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 10] = [
+        Mode::Mandelbrot,
+        Mode::Julia,
+        Mode::BurningShip,
+        Mode::BurningShipJulia,
+        Mode::Tricorn,
+        Mode::TricornJulia,
+        Mode::Celtic,
+        Mode::CelticJulia,
+        Mode::Perpendicular,
+        Mode::PerpendicularJulia,
+    ];
+
+    pub fn formula(self) -> &'static Formula {
+        match self {
+            Mode::Mandelbrot => &MANDELBROT,
+            Mode::Julia => &JULIA,
+            Mode::BurningShip => &BURNING_SHIP,
+            Mode::BurningShipJulia => &BURNING_SHIP_JULIA,
+            Mode::Tricorn => &TRICORN,
+            Mode::TricornJulia => &TRICORN_JULIA,
+            Mode::Celtic => &CELTIC,
+            Mode::CelticJulia => &CELTIC_JULIA,
+            Mode::Perpendicular => &PERPENDICULAR,
+            Mode::PerpendicularJulia => &PERPENDICULAR_JULIA,
+        }
+    }
+
+    /// The mode whose formula is called `name`, in any case and with any
+    /// separators between words, like `burning-ship`.
+    pub fn from_name(name: &str) -> Option<Mode> {
+        let words = |name: &str| {
+            name.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .map(|c| c.to_ascii_lowercase())
+                .collect::<String>()
+        };
+        Mode::ALL
+            .into_iter()
+            .find(|mode| words(mode.formula().name) == words(name))
+    }
+
+    /// The name of the formula in lower case with dashes between words, for
+    /// command lines and links.
+    pub fn slug(self) -> String {
+        self.formula().name.to_ascii_lowercase().replace(' ', "-")
+    }
+
+    /// The parameter plane and the dynamical plane of the variant of this
+    /// mode, as plotted side by side by the dual view.
+    pub fn dual(self) -> [Mode; 2] {
+        let variant = self.formula().variant;
+        [Plane::Parameter, Plane::Dynamical].map(|plane| {
+            Mode::ALL
+                .into_iter()
+                .find(|mode| mode.formula().variant == variant && mode.formula().plane == plane)
+                .unwrap()
+        })
+    }
+}
+
+/// Describes one fractal family.
+///
+/// `shader` is appended to `fractal_shader.wgsl` and must define
+/// `fn start(point: vec2<f32>) -> Orbit` and its df64 twin
+/// `fn start_df(point: DfComplex) -> DfOrbit`, picking the starting `z` and the
+/// `c` of the iteration, and their derivatives, for a point of the plane as
+/// described by `plane`. The iteration itself is picked by `variant`.
+pub struct Formula {
+    pub name: &'static str,
+    pub shader: &'static str,
+    pub plane: Plane,
+    pub variant: Variant,
+    /// The `[min, max]` rectangle outside of which nothing is drawn, and
+    /// around which the home view is.
+    pub domain: [[f32; 2]; 2],
+}
+
+/// The iteration `z -> f(z)^2 + c` of a formula, where `f` folds `z` by
+/// flipping the signs of its parts. The Celtic variant folds the square
+/// instead.
+///
+/// Only `Mandelbrot` is holomorphic: the derivative of the others is not a
+/// complex number, and the one tracked for the distance estimate is only that
+/// along the real axis.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Variant {
+    /// `z -> z^2 + c`.
+    Mandelbrot = 0,
+    /// `z -> (|x| + i|y|)^2 + c`, upside down as the imaginary axis points up.
+    BurningShip = 1,
+    /// `z -> conj(z)^2 + c`, also known as the Mandelbar.
+    Tricorn = 2,
+    /// `z -> |x^2 - y^2| + 2ixy + c`.
+    Celtic = 3,
+    /// `z -> (|x| - iy)^2 + c`.
+    Perpendicular = 4,
+}
+
+impl Variant {
+    /// The signs by which `z` is folded before it is squared, see
+    /// `fold_signs()` in the shader.
+    fn fold_signs(self, z: [f64; 2]) -> [f64; 2] {
+        let sign = |v: f64| if v < 0.0 { -1.0 } else { 1.0 };
+        match self {
+            Variant::Mandelbrot | Variant::Celtic => [1.0, 1.0],
+            Variant::BurningShip => z.map(sign),
+            Variant::Tricorn => [1.0, -1.0],
+            Variant::Perpendicular => [sign(z[0]), -1.0],
+        }
+    }
+
+    /// `z -> f(z)^2 + c`, the CPU counterpart of the shader's `iter()`.
+    pub fn iter(self, z: [f64; 2], c: [f64; 2]) -> [f64; 2] {
+        let [sx, sy] = self.fold_signs(z);
+        let [x, y] = [sx * z[0], sy * z[1]];
+        let mut re = x * x - y * y;
+        if self == Variant::Celtic {
+            re = re.abs();
+        }
+        [re + c[0], 2.0 * x * y + c[1]]
+    }
+
+    /// The derivative of `iter()` along `dz`: `dz -> 2 f(z) f(dz) + dc`, see
+    /// `derivative()` in the shader.
+    pub fn derivative(self, z: [f64; 2], dz: [f64; 2], dc: f64) -> [f64; 2] {
+        let [sx, sy] = self.fold_signs(z);
+        let [x, y] = [sx * z[0], sy * z[1]];
+        let [dx, dy] = [sx * dz[0], sy * dz[1]];
+        let mut re = 2.0 * (x * dx - y * dy);
+        if self == Variant::Celtic && x * x < y * y {
+            re = -re;
+        }
+        [re + dc, 2.0 * (x * dy + y * dx)]
+    }
+
+    /// Whether perturbation, which expands `z -> z^2 + c` around a reference
+    /// orbit, applies.
+    pub fn supports_perturbation(self) -> bool {
+        self == Variant::Mandelbrot
+    }
+
+    /// The WGSL constant picking the variant in `fractal_shader.wgsl`.
+    fn shader_constant(self) -> String {
+        format!("const VARIANT: u32 = {}u;", self as u32)
+    }
+}
+
+/// Which variable of the iteration `z -> f(z)^2 + c` the plotted plane is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Plane {
+    /// The parameter plane: `z` starts at the critical point 0 and `c` is the
+    /// point of the plane.
+    Parameter,
+    /// The dynamical plane: `z` starts at the point of the plane and `c` is
+    /// the user-controlled constant.
+    Dynamical,
+}
+
+/// The starting `z` and the `c` of the iteration `z -> z^2 + c`, with the
+/// derivatives of `z` and `c` with respect to the point of the plane.
+pub struct Orbit {
+    pub z: [f64; 2],
+    pub c: [f64; 2],
+    pub dz: [f64; 2],
+    pub dc: f64,
+}
+
+pub const MANDELBROT: Formula = Formula {
+    name: "Mandelbrot",
+    shader: include_str!("mandelbrot_shader.wgsl"),
+    plane: Plane::Parameter,
+    variant: Variant::Mandelbrot,
+    domain: [[-2.0, -1.25], [0.5, 1.25]],
+};
+
+pub const JULIA: Formula = Formula {
+    name: "Julia",
+    shader: include_str!("julia_shader.wgsl"),
+    plane: Plane::Dynamical,
+    variant: Variant::Mandelbrot,
+    domain: [[-2.0, -2.0], [2.0, 2.0]],
+};
+
+pub const BURNING_SHIP: Formula = Formula {
+    name: "Burning Ship",
+    shader: include_str!("mandelbrot_shader.wgsl"),
+    plane: Plane::Parameter,
+    variant: Variant::BurningShip,
+    domain: [[-2.0, -1.8], [1.2, 0.6]],
+};
+
+pub const BURNING_SHIP_JULIA: Formula = Formula {
+    name: "Burning Ship Julia",
+    shader: include_str!("julia_shader.wgsl"),
+    plane: Plane::Dynamical,
+    variant: Variant::BurningShip,
+    domain: [[-2.0, -2.0], [2.0, 2.0]],
+};
+
+pub const TRICORN: Formula = Formula {
+    name: "Tricorn",
+    shader: include_str!("mandelbrot_shader.wgsl"),
+    plane: Plane::Parameter,
+    variant: Variant::Tricorn,
+    domain: [[-2.0, -1.75], [1.0, 1.75]],
+};
+
+pub const TRICORN_JULIA: Formula = Formula {
+    name: "Tricorn Julia",
+    shader: include_str!("julia_shader.wgsl"),
+    plane: Plane::Dynamical,
+    variant: Variant::Tricorn,
+    domain: [[-2.0, -2.0], [2.0, 2.0]],
+};
+
+pub const CELTIC: Formula = Formula {
+    name: "Celtic",
+    shader: include_str!("mandelbrot_shader.wgsl"),
+    plane: Plane::Parameter,
+    variant: Variant::Celtic,
+    domain: [[-2.0, -1.85], [0.5, 1.85]],
+};
+
+pub const CELTIC_JULIA: Formula = Formula {
+    name: "Celtic Julia",
+    shader: include_str!("julia_shader.wgsl"),
+    plane: Plane::Dynamical,
+    variant: Variant::Celtic,
+    domain: [[-2.0, -2.0], [2.0, 2.0]],
+};
+
+pub const PERPENDICULAR: Formula = Formula {
+    name: "Perpendicular",
+    shader: include_str!("mandelbrot_shader.wgsl"),
+    plane: Plane::Parameter,
+    variant: Variant::Perpendicular,
+    domain: [[-2.0, -1.25], [0.8, 1.25]],
+};
+
+pub const PERPENDICULAR_JULIA: Formula = Formula {
+    name: "Perpendicular Julia",
+    shader: include_str!("julia_shader.wgsl"),
+    plane: Plane::Dynamical,
+    variant: Variant::Perpendicular,
+    domain: [[-2.0, -2.0], [2.0, 2.0]],
+};
+
+impl Formula {
+    /// Whether `Precision::Perturbation` applies to the formula.
+    pub fn supports_perturbation(&self) -> bool {
+        self.variant.supports_perturbation()
+    }
+
+    /// Whether the formula reads the user-controlled constant `c`.
+    pub fn uses_c(&self) -> bool {
+        self.plane == Plane::Dynamical
+    }
+
+    /// The CPU counterpart of the shader's `start()`.
+    pub fn start(&self, point: [f64; 2], c: [f64; 2]) -> Orbit {
+        match self.plane {
+            Plane::Parameter => Orbit {
+                z: [0.0, 0.0],
+                c: point,
+                dz: [0.0, 0.0],
+                dc: 1.0,
+            },
+            Plane::Dynamical => Orbit {
+                z: point,
+                c,
+                dz: [1.0, 0.0],
+                dc: 0.0,
+            },
+        }
+    }
+
+    /// Two triangles covering the part of `domain` inside `view`, in
+    /// normalized device coordinates.
+    pub fn vertices(&self, view: &View, dimensions: [u32; 2]) -> [Vertex; VERTEX_NUM] {
+        let half_extent = [
+            view.pixel_size(dimensions)
+                .mul(RealExp::new(f64::from(dimensions[0]) / 2.0)),
+            view.height().mul(RealExp::new(0.5)),
+        ];
+        let [[x0, y0], [x1, y1]] = self.domain.map(|corner| {
+            [0, 1].map(|i| {
+                let offset = Real::try_from(f64::from(corner[i])).unwrap() - &view.center[i];
+                RealExp::from_real(&offset)
+                    .div(half_extent[i])
+                    .to_f64()
+                    .clamp(-1.0, 1.0) as f32
+            })
+        });
+        [[x0, y0], [x1, y0], [x1, y1], [x0, y0], [x1, y1], [x0, y1]]
+            .map(|position| Vertex { position })
+    }
+}
+
+/// Everything an image depends on but its size.
+#[derive(Clone)]
+pub struct Scene {
+    pub mode: Mode,
+    pub view: View,
+    pub c: [f32; 2],
+    pub max_iterations: u32,
+    pub escape_radius: f32,
+    pub coloring: Coloring,
+    pub palette: Palette,
+    /// Width of the darkened exterior along the boundary, in pixels.
+    pub boundary: f32,
+    pub interior: Interior,
+    /// The interior gradient.
+    pub interior_colors: Vec<[f32; 4]>,
+    pub trap: Trap,
+}
+
+/// Floating point arithmetic used by the escape-time loop of the shaders.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Precision {
+    /// Plain f32, good down to a view width of roughly 1e-5.
+    Single = 0,
+    /// Double-single (df64): unevaluated sums of two f32, which keep about
+    /// 48 bits of mantissa.
+    Double = 1,
+    /// Deltas from an arbitrary-precision reference orbit, see
+    /// `perturbation.wgsl`. Needs storage buffers in fragment shaders.
+    Perturbation = 2,
+}
+
+impl Precision {
+    /// Picks `Double` as soon as the spacing between pixels comes within a
+    /// few f32 ulps of the coordinates of the view, and `Perturbation`, when
+    /// `perturbation` is supported, once it comes within a few df64 ulps.
+    pub fn for_view(view: &View, dimensions: [u32; 2], perturbation: bool) -> Precision {
+        let pixel_size = view.pixel_size(dimensions).log2();
+        // Orbits range over |z| <= 2 whatever the view, so that is the
+        // smallest magnitude to compare against.
+        let bounds = view.bounds(dimensions);
+        let magnitude = [bounds.min(), bounds.max()]
+            .iter()
+            .flatten()
+            .fold(2.0_f64, |magnitude, v| magnitude.max(v.abs()));
+        if perturbation && pixel_size < (DF64_EPSILON * magnitude).log2() {
+            Precision::Perturbation
+        } else if pixel_size < (4.0 * f64::from(f32::EPSILON) * magnitude).log2() {
+            Precision::Double
+        } else {
+            Precision::Single
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Precision::Single => "f32",
+            Precision::Double => "df64",
+            Precision::Perturbation => "perturbation",
+        }
+    }
+}
+
+/// Largest escape radius offered; reference orbits are computed until they
+/// leave it.
+pub const MAX_ESCAPE_RADIUS: f32 = 1e4;
+
+/// Largest `max_iterations` offered.
+pub const MAX_ITERATIONS: u32 = 65536;
+
+/// Splits `v` into the (hi, lo) pair of f32 used by the df64 shader code.
+pub fn df64(v: &Real) -> [f32; 2] {
+    let hi = v.to_f32().value();
+    let lo = (v - Real::try_from(f64::from(hi)).unwrap())
+        .to_f32()
+        .value();
+    [hi, lo]
+}
+
+/// The GPU resources of one formula, stored in egui's callback resources
+/// keyed by `Mode`.
+#[derive(Default)]
+pub struct FractalRenderers(HashMap<Mode, FractalRenderer>);
+
+impl FractalRenderers {
+    pub fn insert(&mut self, mode: Mode, renderer: FractalRenderer) {
+        self.0.insert(mode, renderer);
+    }
+    pub fn get_mut(&mut self, mode: Mode) -> &mut FractalRenderer {
+        self.0.get_mut(&mode).expect("no renderer for mode")
+    }
+}
+
+/// Renders one formula in two passes: the iteration pass writes the escape
+/// data of every pixel to `data_texture`, and the coloring pass maps it onto
+/// the palette. The iteration pass is skipped when only the coloring changed.
+pub struct FractalRenderer {
+    pipeline: wgpu::RenderPipeline,
+    target_format: wgpu::TextureFormat,
+    /// Format of `data_texture`, see `coloring::data_format()`.
+    data_format: wgpu::TextureFormat,
+    bind_group: wgpu::BindGroup,
+    /// `None` when the device has no storage buffers in fragment shaders.
+    perturbation: Option<PerturbationPipeline>,
+    coloring_pass: ColoringPass,
+
+    uniform_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+    formula: &'static Formula,
+    /// The inputs of the iteration pass that last ran.
+    iterated: Option<IterationInputs>,
+    iterate: bool,
+    /// Whether the histogram of `Coloring::Histogram` is built this frame, and
+    /// whether it matches the data texture.
+    equalize: bool,
+    equalized: bool,
+    /// The distribution used instead of the histogram, see `set_cdf()`.
+    cdf: Option<Vec<f32>>,
+
+    data_texture: (wgpu::Texture,),
+    texture: (wgpu::Texture,),
+    multisampled_texture: (wgpu::Texture,),
+    width: u32,
+    height: u32,
+
+    palette: Palette,
+    interior: Interior,
+    /// The interior gradient.
+    interior_colors: Vec<[f32; 4]>,
+    max_iterations: u32,
+    c: [f32; 2],
+    escape_radius: f32,
+    coloring: Coloring,
+    /// Width of the darkened exterior along the boundary, in pixels.
+    boundary: f32,
+    trap: Trap,
+    precision: Precision,
+    probe: TexelProbe,
+}
+
+impl FractalRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        data_format: wgpu::TextureFormat,
+        formula: &'static Formula,
+        palette: Palette,
+        max_iterations: u32,
+    ) -> FractalRenderer {
+        let source = format!(
+            "{}\n{}\n{}",
+            include_str!("fractal_shader.wgsl"),
+            formula.variant.shader_constant(),
+            formula.shader
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("egui_plot_line_shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("egui_plot_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("egui_plot_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, "fs_main", data_format);
+
+        let uniform_buffer = <wgpu::Device as wgpu::util::DeviceExt>::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("egui_plot_uniforms"),
+                contents: bytemuck::cast_slice(&[UniformParams {
+                    x_min: [-1.0, 0.0],
+                    y_min: [-1.0, 0.0],
+                    extent: [2.0, 2.0],
+                    pixel_size: [2.0, 2.0],
+                    max_iterations,
+                    precision: Precision::Single as u32,
+                    c: [0.0, 0.0],
+                    escape_radius: 2.0,
+                    trap_shape: 0,
+                    trap_center: [0.0, 0.0],
+                    trap_direction: [1.0, 0.0],
+                    trap_radius: 0.0,
+                    padding0: 0,
+                }]),
+                usage: wgpu::BufferUsages::COPY_DST
+                    // | wgpu::BufferUsages::MAP_WRITE
+                    | wgpu::BufferUsages::UNIFORM,
+            },
+        );
+
+        #[allow(clippy::useless_vec)]
+        let vertex_buffer = <wgpu::Device as wgpu::util::DeviceExt>::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("egui_plot_vertices"),
+                contents: bytemuck::cast_slice(&vec![Vertex::default(); VERTEX_NUM]),
+                usage: wgpu::BufferUsages::COPY_DST
+                    // | wgpu::BufferUsages::MAP_WRITE
+                    | wgpu::BufferUsages::VERTEX,
+            },
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("egui_plot_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let perturbation = (device.limits().max_storage_buffers_per_shader_stage > 0
+            && formula.supports_perturbation())
+        .then(|| PerturbationPipeline::new(device, formula, &uniform_buffer, data_format));
+
+        // Allocate some stand-in textures since we don't know the final width
+        // and height yet.
+        let data_texture =
+            Self::create_texture(device, data_format, 1, DEFAULT_WIDTH, DEFAULT_HEIGHT);
+        let coloring_pass = ColoringPass::new(
+            device,
+            target_format,
+            &data_texture.0.create_view(&Default::default()),
+        );
+        let texture = Self::create_texture(
+            device,
+            target_format,
+            MSAA_SAMPLE_COUNT,
+            DEFAULT_WIDTH,
+            DEFAULT_HEIGHT,
+        );
+        let multisampled_texture = Self::create_texture(
+            device,
+            target_format,
+            MSAA_SAMPLE_COUNT,
+            DEFAULT_WIDTH,
+            DEFAULT_HEIGHT,
+        );
+
+        FractalRenderer {
+            pipeline,
+            target_format,
+            data_format,
+            bind_group,
+            perturbation,
+            coloring_pass,
+            uniform_buffer,
+            vertex_buffer,
+            formula,
+            iterated: None,
+            iterate: true,
+            equalize: false,
+            equalized: false,
+            cdf: None,
+            data_texture,
+            texture,
+            multisampled_texture,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            palette,
+            interior: Interior::Black,
+            interior_colors: vec![[0.0, 0.0, 0.0, 1.0]],
+            max_iterations: max_iterations.min(coloring::max_iterations(data_format)),
+            c: [0.0, 0.0],
+            escape_radius: 2.0,
+            coloring: Coloring::Banded,
+            boundary: 0.0,
+            trap: Trap::default(),
+            precision: Precision::Single,
+            probe: TexelProbe::new(device),
+        }
+    }
+    /// A renderer set up for `scene`.
+    pub fn for_scene(
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        data_format: wgpu::TextureFormat,
+        scene: &Scene,
+    ) -> FractalRenderer {
+        let mut renderer = FractalRenderer::new(
+            device,
+            target_format,
+            data_format,
+            scene.mode.formula(),
+            scene.palette.clone(),
+            scene.max_iterations,
+        );
+        renderer.set_scene(scene);
+        renderer
+    }
+    /// Sets everything but the view and the mode from `scene`.
+    pub fn set_scene(&mut self, scene: &Scene) {
+        self.set_palette(&scene.palette);
+        self.set_max_iterations(scene.max_iterations);
+        self.set_c(scene.c);
+        self.set_escape_radius(scene.escape_radius);
+        self.set_coloring(scene.coloring);
+        self.set_boundary(scene.boundary);
+        self.set_interior(scene.interior, &scene.interior_colors);
+        self.set_trap(scene.trap);
+    }
+    pub fn set_palette(&mut self, palette: &Palette) {
+        if self.palette != *palette {
+            self.palette = palette.clone();
+        }
+    }
+    pub fn set_interior(&mut self, interior: Interior, colors: &[[f32; 4]]) {
+        self.interior = interior;
+        if self.interior_colors != colors {
+            self.interior_colors = colors.to_vec();
+        }
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        sample_count: u32,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::Texture,) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("egui_plot_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: target_format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: Default::default(),
+        });
+        (texture,)
+    }
+    /// The colored image, of the target format.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture.0
+    }
+    /// Copies the colored image and the data texture back, waiting for the
+    /// GPU.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_back(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> readback::Readback {
+        let rgba = readback::read_texture(device, queue, &self.texture.0);
+        let data = readback::read_texture(device, queue, &self.data_texture.0);
+        readback::Readback {
+            size: [self.width, self.height],
+            rgba: readback::rgba_texels(self.target_format, &rgba),
+            data: readback::data_texels(self.data_format, &data),
+        }
+    }
+    /// Starts reading `pixel`, from the top left, of the last render back,
+    /// unless a read is in flight. See `probed_texel()`.
+    pub fn probe_texel(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, pixel: [u32; 2]) {
+        self.probe
+            .request(device, queue, &self.texture.0, &self.data_texture.0, pixel);
+    }
+    /// The last pixel read by `probe_texel()`, and whether a read is still in
+    /// flight.
+    pub fn probed_texel(&mut self, device: &wgpu::Device) -> (Option<Texel>, bool) {
+        let texel = self
+            .probe
+            .poll(device, &self.texture.0, &self.data_texture.0);
+        (texel, self.probe.is_pending())
+    }
+    pub fn create_view(&self) -> wgpu::TextureView {
+        self.texture
+            .0
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+    fn create_multisampled_view(&self) -> wgpu::TextureView {
+        self.multisampled_texture
+            .0
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// Whether `Coloring::Histogram` is available on this device.
+    pub fn supports_histogram(&self) -> bool {
+        self.coloring_pass.supports_histogram()
+    }
+
+    /// Whether `Precision::Perturbation` is available for the formula on this
+    /// device.
+    pub fn supports_perturbation(&self) -> bool {
+        self.perturbation.is_some()
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        dimensions: [u32; 2],
+        view: &View,
+        precision: Precision,
+        perturbation: Option<&PerturbationFrame>,
+    ) {
+        // Re-allocate the render targets if the requested dimensions have changed.
+        if dimensions[0] != self.width || dimensions[1] != self.height {
+            self.width = dimensions[0];
+            self.height = dimensions[1];
+
+            self.data_texture =
+                Self::create_texture(device, self.data_format, 1, self.width, self.height);
+            self.coloring_pass.set_data(
+                device,
+                &self.data_texture.0.create_view(&Default::default()),
+            );
+            self.texture = Self::create_texture(
+                device,
+                self.target_format,
+                MSAA_SAMPLE_COUNT,
+                self.width,
+                self.height,
+            );
+            self.multisampled_texture = Self::create_texture(
+                device,
+                self.target_format,
+                MSAA_SAMPLE_COUNT,
+                self.width,
+                self.height,
+            );
+        }
+
+        // Fall back to df64 until a reference orbit is ready.
+        self.precision = match (precision, perturbation, &mut self.perturbation) {
+            (Precision::Perturbation, Some(frame), Some(pipeline)) => {
+                pipeline.upload(device, queue, &self.uniform_buffer, frame);
+                Precision::Perturbation
+            }
+            (Precision::Perturbation, _, _) => Precision::Double,
+            (precision, _, _) => precision,
+        };
+        // Only the corner needs the digits of the center, the extent of the
+        // view is tiny next to it.
+        let bounds = view.bounds(dimensions);
+        let corner = |i: usize, half_extent: f64| {
+            df64(&(&view.center[i] - Real::try_from(half_extent).unwrap()))
+        };
+        let inputs = IterationInputs {
+            uniforms: UniformParams {
+                x_min: corner(0, bounds.width() / 2.0),
+                y_min: corner(1, bounds.height() / 2.0),
+                extent: [bounds.width() as f32, bounds.height() as f32],
+                pixel_size: [
+                    (bounds.width() / f64::from(self.width)) as f32,
+                    (bounds.height() / f64::from(self.height)) as f32,
+                ],
+                max_iterations: self.max_iterations,
+                precision: self.precision as u32,
+                c: self.c,
+                escape_radius: self.escape_radius,
+                trap_shape: self.trap.shape as u32,
+                trap_center: self.trap.center,
+                trap_direction: self.trap.direction(),
+                trap_radius: self.trap.radius,
+                padding0: 0,
+            },
+            vertices: self.formula.vertices(view, dimensions),
+            dimensions,
+            perturbation: perturbation
+                .filter(|_| self.precision == Precision::Perturbation)
+                .map(|frame| (frame.params, Arc::clone(&frame.reference))),
+        };
+        self.iterate = self.iterated.as_ref() != Some(&inputs);
+        if self.iterate {
+            queue.write_buffer(
+                &self.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[inputs.uniforms]),
+            );
+            queue.write_buffer(
+                &self.vertex_buffer,
+                0,
+                bytemuck::cast_slice(&inputs.vertices),
+            );
+            self.iterated = Some(inputs);
+        }
+
+        let params = ColoringParams {
+            interior: self.interior as u32,
+            ..self
+                .palette
+                .params(self.coloring, self.boundary, self.trap.stalk_width())
+        };
+        self.coloring_pass.prepare(
+            device,
+            queue,
+            &params,
+            &self.palette,
+            &self.interior_colors,
+            self.max_iterations,
+        );
+        match &self.cdf {
+            Some(cdf) => {
+                self.coloring_pass.set_cdf(queue, cdf);
+                self.equalize = false;
+            }
+            None => {
+                self.equalized &= !self.iterate;
+                self.equalize = self.coloring == Coloring::Histogram && !self.equalized;
+                self.equalized |= self.equalize;
+            }
+        }
+    }
+
+    /// Colors `Coloring::Histogram` with the distribution `cdf`, one value
+    /// per iteration count, instead of that of the image itself, or goes back
+    /// to it with `None`.
+    pub fn set_cdf(&mut self, cdf: Option<Vec<f32>>) {
+        self.cdf = cdf;
+        self.equalized = false;
+    }
+
+    pub fn render(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        if self.iterate {
+            let view = self.data_texture.0.create_view(&Default::default());
+            let [r, g, b, a] = OUTSIDE.map(f64::from);
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color { r, g, b, a }),
+                        store: Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.render_onto_renderpass(&mut rpass);
+        }
+        if self.equalize {
+            self.coloring_pass.equalize(&mut encoder);
+        }
+        {
+            let view = self.create_view();
+            let msaa_view = self.create_multisampled_view();
+
+            // Render directly to the texture if no MSAA, or use the
+            // multisampled buffer and resolve to the texture if using MSAA.
+            let rpass_color_attachment = if MSAA_SAMPLE_COUNT == 1 {
+                wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: Store,
+                    },
+                }
+            } else {
+                wgpu::RenderPassColorAttachment {
+                    view: &msaa_view,
+                    depth_slice: None,
+                    resolve_target: Some(&view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: Store,
+                    },
+                }
+            };
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(rpass_color_attachment)],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.coloring_pass.render_onto_renderpass(&mut rpass);
+        }
+
+        queue.submit(core::iter::once(encoder.finish()));
+    }
+
+    /// Records the iteration pass, writing to a target of `data_format`.
+    pub fn render_onto_renderpass<'rp>(&'rp self, rpass: &mut wgpu::RenderPass<'rp>) {
+        match (&self.perturbation, self.precision) {
+            (Some(perturbation), Precision::Perturbation) => {
+                rpass.set_pipeline(&perturbation.pipeline);
+                rpass.set_bind_group(0, &perturbation.bind_group, &[]);
+            }
+            _ => {
+                rpass.set_pipeline(&self.pipeline);
+                rpass.set_bind_group(0, &self.bind_group, &[]);
+            }
+        }
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(0..VERTEX_NUM as u32, 0..1);
+    }
+    /// Sets `max_iterations`, at most what the data texture holds, see
+    /// `coloring::max_iterations()`.
+    pub fn set_max_iterations(&mut self, max_iterations: u32) {
+        self.max_iterations = max_iterations.min(coloring::max_iterations(self.data_format));
+    }
+    pub fn max_iterations(&self) -> u32 {
+        self.max_iterations
+    }
+    pub fn c(&self) -> [f32; 2] {
+        self.c
+    }
+    pub fn set_c(&mut self, c: [f32; 2]) {
+        self.c = c;
+    }
+    pub fn set_escape_radius(&mut self, escape_radius: f32) {
+        self.escape_radius = escape_radius;
+    }
+    pub fn set_coloring(&mut self, coloring: Coloring) {
+        self.coloring = coloring;
+    }
+    pub fn set_boundary(&mut self, boundary: f32) {
+        self.boundary = boundary;
+    }
+    pub fn set_trap(&mut self, trap: Trap) {
+        self.trap = trap;
+    }
+    /// The precision picked for the last prepared view.
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fragment_entry_point: &str,
+    data_format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("egui_plot_pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x2],
+            }],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(fragment_entry_point),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: data_format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Everything the output of the iteration pass depends on.
+struct IterationInputs {
+    uniforms: UniformParams,
+    vertices: [Vertex; VERTEX_NUM],
+    dimensions: [u32; 2],
+    /// The parameters and the reference orbit, compared by identity.
+    perturbation: Option<(PerturbationParams, Arc<ReferenceOrbit>)>,
+}
+
+impl PartialEq for IterationInputs {
+    fn eq(&self, other: &Self) -> bool {
+        let perturbation = match (&self.perturbation, &other.perturbation) {
+            (Some((params, reference)), Some((other_params, other_reference))) => {
+                params == other_params && Arc::ptr_eq(reference, other_reference)
+            }
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        self.uniforms == other.uniforms
+            && self.vertices == other.vertices
+            && self.dimensions == other.dimensions
+            && perturbation
+    }
+}
+
+/// A reference orbit and where the view sits relative to it.
+pub struct PerturbationFrame {
+    pub reference: Arc<ReferenceOrbit>,
+    pub params: PerturbationParams,
+}
+
+impl PerturbationFrame {
+    /// Places `view`, in an image of `dimensions`, relative to `reference`.
+    pub fn new(
+        reference: Arc<ReferenceOrbit>,
+        view: &View,
+        dimensions: [u32; 2],
+        use_series: bool,
+    ) -> PerturbationFrame {
+        let pixel = view.pixel_size(dimensions);
+        // In pixels from the center, rows going down.
+        let offset = [0, 1].map(|i| {
+            RealExp::from_real(&(&reference.key.point[i] - &view.center[i]))
+                .div(pixel)
+                .to_f64()
+        });
+        let params = reference.params(
+            &PerturbationView {
+                reference_pixel: [
+                    f64::from(dimensions[0]) / 2.0 + offset[0],
+                    f64::from(dimensions[1]) / 2.0 - offset[1],
+                ],
+                pixel_size: [pixel; 2],
+                dimensions,
+            },
+            use_series,
+        );
+        PerturbationFrame { reference, params }
+    }
+}
+
+/// The pipeline of `perturbation.wgsl`, with the reference orbit uploaded as
+/// a storage buffer.
+struct PerturbationPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    reference_buffer: wgpu::Buffer,
+    /// The orbit currently in `reference_buffer`.
+    reference: Option<Arc<ReferenceOrbit>>,
+}
+
+impl PerturbationPipeline {
+    fn new(
+        device: &wgpu::Device,
+        formula: &Formula,
+        uniform_buffer: &wgpu::Buffer,
+        data_format: wgpu::TextureFormat,
+    ) -> PerturbationPipeline {
+        let source = format!(
+            "{}\n{}\n{}\n{}",
+            include_str!("fractal_shader.wgsl"),
+            formula.variant.shader_constant(),
+            formula.shader,
+            include_str!("perturbation.wgsl")
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("egui_plot_perturbation_shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("egui_plot_perturbation_bind_group_layout"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                buffer_entry(1, wgpu::BufferBindingType::Uniform),
+                wgpu::BindGroupLayoutEntry {
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ..buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: true })
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("egui_plot_perturbation_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            "fs_perturbation",
+            data_format,
+        );
+
+        let params_buffer = <wgpu::Device as wgpu::util::DeviceExt>::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("egui_plot_perturbation_params"),
+                contents: bytemuck::cast_slice(&[PerturbationParams::default()]),
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            },
+        );
+        let reference_buffer = Self::create_reference_buffer(device, 1);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            uniform_buffer,
+            &params_buffer,
+            &reference_buffer,
+        );
+
+        PerturbationPipeline {
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            params_buffer,
+            reference_buffer,
+            reference: None,
+        }
+    }
+
+    fn create_reference_buffer(device: &wgpu::Device, len: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("egui_plot_reference_orbit"),
+            size: (len * std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        params_buffer: &wgpu::Buffer,
+        reference_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("egui_plot_perturbation_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: reference_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Writes the per-view parameters, and the orbit if it changed.
+    fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniform_buffer: &wgpu::Buffer,
+        frame: &PerturbationFrame,
+    ) {
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[frame.params]),
+        );
+        if self
+            .reference
+            .as_ref()
+            .is_some_and(|reference| Arc::ptr_eq(reference, &frame.reference))
+        {
+            return;
+        }
+        let orbits = &frame.reference.orbits;
+        if self.reference_buffer.size() < std::mem::size_of_val(orbits.as_slice()) as u64 {
+            self.reference_buffer = Self::create_reference_buffer(device, orbits.len());
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                uniform_buffer,
+                &self.params_buffer,
+                &self.reference_buffer,
+            );
+        }
+        queue.write_buffer(&self.reference_buffer, 0, bytemuck::cast_slice(orbits));
+        self.reference = Some(Arc::clone(&frame.reference));
+    }
+}
+
+pub struct FractalCallback {
+    pub mode: Mode,
+    pub view: View,
+    pub rect: egui::Rect,
+    pub precision: Precision,
+    pub perturbation: Option<PerturbationFrame>,
+    /// The pixel to read back after rendering, see `probe_texel()`.
+    pub probe: Option<[u32; 2]>,
+}
+
+impl egui_wgpu::CallbackTrait for FractalCallback {
+    fn prepare(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        _screen_descriptor: &ScreenDescriptor,
+        _egui_encoder: &mut CommandEncoder,
+        paint_callback_resources: &mut CallbackResources,
+    ) -> Vec<CommandBuffer> {
+        let renderers: &mut FractalRenderers = paint_callback_resources.get_mut().unwrap();
+        let util = renderers.get_mut(self.mode);
+
+        util.prepare(
+            device,
+            queue,
+            [self.rect.width() as u32, self.rect.height() as u32],
+            &self.view,
+            self.precision,
+            self.perturbation.as_ref(),
+        );
+        util.render(device, queue);
+        if let Some(pixel) = self.probe {
+            util.probe_texel(device, queue, pixel);
+        }
+        vec![]
+    }
+
+    #[allow(unused_variables)]
+    fn paint(
+        &self,
+        info: PaintCallbackInfo,
+        render_pass: &mut RenderPass<'static>,
+        callback_resources: &CallbackResources,
+    ) {
+        // todo!()
+    }
+}
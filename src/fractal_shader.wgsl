@@ -0,0 +1,373 @@
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+};
+
+const PRECISION_SINGLE: u32 = 0u;
+const PRECISION_DOUBLE: u32 = 1u;
+
+// Values of `VARIANT`, which is defined per formula, see `Variant`.
+const VARIANT_BURNING_SHIP: u32 = 1u;
+const VARIANT_TRICORN: u32 = 2u;
+const VARIANT_CELTIC: u32 = 3u;
+const VARIANT_PERPENDICULAR: u32 = 4u;
+
+const TRAP_LINE: u32 = 1u;
+const TRAP_CROSS: u32 = 2u;
+const TRAP_CIRCLE: u32 = 3u;
+const TRAP_STALKS: u32 = 4u;
+
+// The trap distance of orbits that never came near the trap.
+const TRAP_FAR: f32 = 1e30;
+
+// How close an orbit has to come back to a point to be taken as a cycle.
+const PERIOD_TOLERANCE: f32 = 1e-6;
+
+struct UniformParams {
+    // (hi, lo) pairs of the bottom-left corner of the view.
+    x_min: vec2<f32>,
+    y_min: vec2<f32>,
+    extent: vec2<f32>,
+    pixel_size: vec2<f32>,
+    max_iterations: u32,
+    precision_mode: u32,
+    c: vec2<f32>,
+    escape_radius: f32,
+    trap_shape: u32,
+    trap_center: vec2<f32>,
+    trap_direction: vec2<f32>,
+    trap_radius: f32,
+    padding0: u32,
+};
+
+// The starting point of the iteration z -> f(z)^2 + c, picked per formula by
+// `start()`, with the derivatives of z and c with respect to the point of the
+// plane.
+struct Orbit {
+    z: vec2<f32>,
+    c: vec2<f32>,
+    dz: vec2<f32>,
+    dc: f32,
+};
+
+// A complex number whose parts are df64 (hi, lo) pairs.
+struct DfComplex {
+    re: vec2<f32>,
+    im: vec2<f32>,
+};
+
+// `Orbit` in df64, picked per formula by `start_df()`. The derivatives only
+// need f32.
+struct DfOrbit {
+    z: DfComplex,
+    c: DfComplex,
+    dz: vec2<f32>,
+    dc: f32,
+};
+
+struct Escape {
+    escaped: bool,
+    iterations: u32,
+    // The first point outside of the escape radius, or the last point of
+    // orbits that did not escape.
+    z: vec2<f32>,
+    // The exterior distance estimate at `z`, in pixels.
+    distance: f32,
+    // The smallest `trap_distance()` along the orbit.
+    trap: f32,
+    // The attracting cycle found by `periodicity_step()` in orbits that did
+    // not escape, with a period of 0 if none.
+    period: u32,
+    multiplier: f32,
+};
+
+// Brent's cycle detection: every point of the orbit is compared with the last
+// one at a power of two iterations, `reference`. See Brent, "An improved Monte
+// Carlo factorization algorithm" (1980).
+struct Periodicity {
+    reference: vec2<f32>,
+    reference_index: u32,
+    // log |d z_n / d z_reference|, the sum of log |2 z| since `reference`:
+    // folding `z` does not change the magnitude of the derivative.
+    log_derivative: f32,
+    // Whether the orbit came back to `reference` already.
+    matched: bool,
+    period: u32,
+    // |d z_period / d z| over one cycle.
+    multiplier: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: UniformParams;
+
+// The vertices are the part of the formula's domain inside the view, already
+// in normalized device coordinates: the view can be too small for f32.
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> VertexOut {
+    var out: VertexOut;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+// The iteration pass writes `escape_data()` of each pixel to a float texture,
+// which `coloring.wgsl` then maps onto the palette.
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    var result: Escape;
+    if (uniforms.precision_mode == PRECISION_DOUBLE) {
+        result = escape_df(start_df(df_point(in.position.xy)));
+    } else {
+        result = escape(start(point(in.position.xy)));
+    }
+    return escape_data(result);
+}
+
+// (iterations, smooth iteration count, distance estimate, trap distance), or
+// (0, period, final |z|, |multiplier|) if not escaped.
+fn escape_data(result: Escape) -> vec4<f32> {
+    if (!result.escaped) {
+        return vec4<f32>(0.0, f32(result.period), length(result.z), result.multiplier);
+    }
+    return vec4<f32>(
+        f32(result.iterations),
+        smooth_iterations(result),
+        result.distance,
+        result.trap,
+    );
+}
+
+fn escaped(iterations: u32, z: vec2<f32>, distance: f32, trap: f32) -> Escape {
+    return Escape(true, iterations, z, distance, trap, 0u, 0.0);
+}
+
+fn no_escape(z: vec2<f32>, trap: f32, periodicity: Periodicity) -> Escape {
+    return Escape(false, 0u, z, 0.0, trap, periodicity.period, periodicity.multiplier);
+}
+
+fn periodicity_new() -> Periodicity {
+    return Periodicity(vec2<f32>(0.0, 0.0), 0u, 0.0, true, 0u, 0.0);
+}
+
+// Looks for a cycle at `z`, the point of the orbit after `i` iterations.
+fn periodicity_step(state: ptr<function, Periodicity>, i: u32, z: vec2<f32>) {
+    let offset = z - (*state).reference;
+    if (!(*state).matched && dot(offset, offset) < PERIOD_TOLERANCE * PERIOD_TOLERANCE) {
+        (*state).matched = true;
+        (*state).period = i - (*state).reference_index;
+        (*state).multiplier = exp((*state).log_derivative);
+    }
+    // Also true for i = 0.
+    if ((i & (i - 1u)) == 0u) {
+        (*state).reference = z;
+        (*state).reference_index = i;
+        (*state).log_derivative = 0.0;
+        (*state).matched = false;
+    }
+    (*state).log_derivative += log(2.0 * length(z));
+}
+
+// The distance from `z` to the orbit trap, see `Trap::distance()`.
+fn trap_distance(z: vec2<f32>) -> f32 {
+    let offset = z - uniforms.trap_center;
+    let direction = uniforms.trap_direction;
+    let along = dot(offset, direction);
+    let across = dot(offset, vec2<f32>(-direction.y, direction.x));
+    if (uniforms.trap_shape == TRAP_LINE) {
+        return abs(across);
+    }
+    if (uniforms.trap_shape == TRAP_CROSS || uniforms.trap_shape == TRAP_STALKS) {
+        return min(abs(along), abs(across));
+    }
+    if (uniforms.trap_shape == TRAP_CIRCLE) {
+        return abs(length(offset) - uniforms.trap_radius);
+    }
+    return length(offset);
+}
+
+fn escape_radius_sqr() -> f32 {
+    return uniforms.escape_radius * uniforms.escape_radius;
+}
+
+fn escape(orbit: Orbit) -> Escape {
+    var z = orbit.z;
+    var dz = orbit.dz;
+    var trap = TRAP_FAR;
+    var periodicity = periodicity_new();
+    for (var i = 0u; i < uniforms.max_iterations; i++) {
+        // The starting point is not part of the orbit: 0 for every point of
+        // the parameter plane.
+        if (i > 0u) {
+            trap = min(trap, trap_distance(z));
+        }
+        if (dot(z, z) > escape_radius_sqr()) {
+            let distance = distance_estimate(z, length(dz) * uniforms.pixel_size.y);
+            return escaped(i + 1u, z, distance, trap);
+        }
+        periodicity_step(&periodicity, i, z);
+        dz = derivative(z, dz, orbit.dc);
+        z = iter(z, orbit.c);
+    }
+    return no_escape(z, trap, periodicity);
+}
+
+// The signs by which `VARIANT` folds `z` before squaring it, 1 or -1 per part.
+fn fold_signs(z: vec2<f32>) -> vec2<f32> {
+    let signs = select(vec2<f32>(-1.0), vec2<f32>(1.0), z >= vec2<f32>(0.0));
+    if (VARIANT == VARIANT_BURNING_SHIP) {
+        return signs;
+    }
+    if (VARIANT == VARIANT_TRICORN) {
+        return vec2<f32>(1.0, -1.0);
+    }
+    if (VARIANT == VARIANT_PERPENDICULAR) {
+        return vec2<f32>(signs.x, -1.0);
+    }
+    return vec2<f32>(1.0, 1.0);
+}
+
+// z -> f(z)^2 + c, where `f` folds `z` as `fold_signs()` says. The Celtic
+// variant folds the real part of the square instead.
+fn iter(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    let f = fold_signs(z) * z;
+    var square = vec2<f32>(f.x * f.x - f.y * f.y, 2.0 * f.x * f.y);
+    if (VARIANT == VARIANT_CELTIC) {
+        square.x = abs(square.x);
+    }
+    return square + c;
+}
+
+// The derivative of `iter()` with respect to the point of the plane:
+// dz -> 2 f(z) f(dz) + dc, along the real axis for the variants that are not
+// holomorphic.
+fn derivative(z: vec2<f32>, dz: vec2<f32>, dc: f32) -> vec2<f32> {
+    let signs = fold_signs(z);
+    let f = signs * z;
+    let df = signs * dz;
+    var product = 2.0 * vec2<f32>(f.x * df.x - f.y * df.y, f.x * df.y + f.y * df.x);
+    if (VARIANT == VARIANT_CELTIC && f.x * f.x < f.y * f.y) {
+        product.x = -product.x;
+    }
+    return product + vec2<f32>(dc, 0.0);
+}
+
+// The exterior distance estimate |z| ln|z| / |dz| from the escaped `z` to the
+// set, given |dz| in units of pixels. See Milnor, "Self-similarity and
+// hairiness in the Mandelbrot set" (1989).
+fn distance_estimate(z: vec2<f32>, dz_norm: f32) -> f32 {
+    // Also catches NaN: derivatives beyond f32 are well within a pixel.
+    if (!(dz_norm < 1e38)) {
+        return 0.0;
+    }
+    let norm_sqr = dot(z, z);
+    return sqrt(norm_sqr) * 0.5 * log(norm_sqr) / dz_norm;
+}
+
+// The normalized iteration count: the fraction by which |z| overshot the
+// escape radius, on a log-log scale, is taken off the iteration count so that
+// it varies continuously across bands.
+fn smooth_iterations(result: Escape) -> f32 {
+    let overshoot = log(dot(result.z, result.z)) / log(escape_radius_sqr());
+    return f32(result.iterations) - log2(overshoot);
+}
+
+// df64 arithmetic: a value is the unevaluated sum hi + lo of two f32, see
+// Dekker, "A floating-point technique for extending the available
+// precision" (1971).
+
+fn two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = a + b;
+    let v = s - a;
+    return vec2<f32>(s, (a - (s - v)) + (b - v));
+}
+
+fn quick_two_sum(a: f32, b: f32) -> vec2<f32> {
+    let s = a + b;
+    return vec2<f32>(s, b - (s - a));
+}
+
+// Splits the 24-bit mantissa of `a` into two halves of 12 bits.
+fn split(a: f32) -> vec2<f32> {
+    let t = 4097.0 * a;
+    let hi = t - (t - a);
+    return vec2<f32>(hi, a - hi);
+}
+
+fn two_prod(a: f32, b: f32) -> vec2<f32> {
+    let p = a * b;
+    let a_split = split(a);
+    let b_split = split(b);
+    let err = ((a_split.x * b_split.x - p) + a_split.x * b_split.y + a_split.y * b_split.x)
+        + a_split.y * b_split.y;
+    return vec2<f32>(p, err);
+}
+
+fn df_add(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let s = two_sum(a.x, b.x);
+    return quick_two_sum(s.x, s.y + a.y + b.y);
+}
+
+fn df_sub(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return df_add(a, -b);
+}
+
+fn df_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let p = two_prod(a.x, b.x);
+    return quick_two_sum(p.x, p.y + a.x * b.y + a.y * b.x);
+}
+
+// The offset of a fragment from the bottom-left corner of the view.
+fn view_offset(position: vec2<f32>) -> vec2<f32> {
+    // Fragment rows go down from the top edge of the view.
+    return vec2<f32>(
+        position.x * uniforms.pixel_size.x,
+        uniforms.extent.y - position.y * uniforms.pixel_size.y,
+    );
+}
+
+// The point of the plane under a fragment.
+fn point(position: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(uniforms.x_min.x, uniforms.y_min.x) + view_offset(position);
+}
+
+// `point()` in df64. Offsets within the view are small enough for f32, only
+// the corner needs df64.
+fn df_point(position: vec2<f32>) -> DfComplex {
+    let offset = view_offset(position);
+    return DfComplex(
+        df_add(uniforms.x_min, vec2<f32>(offset.x, 0.0)),
+        df_add(uniforms.y_min, vec2<f32>(offset.y, 0.0)),
+    );
+}
+
+fn escape_df(orbit: DfOrbit) -> Escape {
+    var z = orbit.z;
+    var dz = orbit.dz;
+    var trap = TRAP_FAR;
+    var periodicity = periodicity_new();
+    for (var i = 0u; i < uniforms.max_iterations; i++) {
+        let hi = vec2<f32>(z.re.x, z.im.x);
+        if (i > 0u) {
+            trap = min(trap, trap_distance(hi));
+        }
+        if (dot(hi, hi) > escape_radius_sqr()) {
+            let distance = distance_estimate(hi, length(dz) * uniforms.pixel_size.y);
+            return escaped(i + 1u, hi, distance, trap);
+        }
+        periodicity_step(&periodicity, i, hi);
+        dz = derivative(hi, dz, orbit.dc);
+        z = iter_df(z, orbit.c);
+    }
+    return no_escape(vec2<f32>(z.re.x, z.im.x), trap, periodicity);
+}
+
+fn iter_df(z: DfComplex, c: DfComplex) -> DfComplex {
+    // Flipping signs is exact in df64, and the sign of a df64 is that of hi.
+    let signs = fold_signs(vec2<f32>(z.re.x, z.im.x));
+    let f = DfComplex(signs.x * z.re, signs.y * z.im);
+    var square_re = df_sub(df_mul(f.re, f.re), df_mul(f.im, f.im));
+    if (VARIANT == VARIANT_CELTIC && square_re.x < 0.0) {
+        square_re = -square_re;
+    }
+    let re = df_add(square_re, c.re);
+    let im = df_add(df_mul(vec2<f32>(2.0, 0.0), df_mul(f.re, f.im)), c.im);
+    return DfComplex(re, im);
+}
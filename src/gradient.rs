@@ -0,0 +1,99 @@
+use colorgrad::Gradient;
+use serde::{Deserialize, Serialize};
+
+/// Number of palette entries sampled from a gradient by default.
+pub const PALETTE_LEN: usize = 128;
+
+/// Largest number of palette entries offered.
+pub const MAX_PALETTE_LEN: usize = 4096;
+
+macro_rules! preset {
+    ($name:ident) => {
+        (Box::new(colorgrad::preset::$name()), stringify!($name))
+    };
+}
+
+/// The preset gradients offered for palettes, with their names.
+pub fn presets() -> Vec<(Box<dyn Gradient>, &'static str)> {
+    vec![
+        preset!(sinebow),
+        preset!(turbo),
+        preset!(cividis),
+        preset!(rainbow),
+        preset!(cubehelix_default),
+        preset!(warm),
+        preset!(cool),
+        preset!(viridis),
+        preset!(inferno),
+        preset!(magma),
+        preset!(plasma),
+        preset!(bu_gn),
+        preset!(bu_pu),
+        preset!(gn_bu),
+        preset!(or_rd),
+        preset!(pu_bu_gn),
+        preset!(pu_bu),
+        preset!(pu_rd),
+        preset!(rd_pu),
+        preset!(yl_gn_bu),
+        preset!(yl_gn),
+        preset!(yl_or_br),
+        preset!(yl_or_rd),
+        preset!(br_bg),
+        preset!(pr_gn),
+        preset!(pi_yg),
+        preset!(pu_or),
+        preset!(rd_bu),
+        preset!(rd_gy),
+        preset!(rd_yl_bu),
+        preset!(rd_yl_gn),
+        preset!(spectral),
+        preset!(blues),
+        preset!(greens),
+        preset!(greys),
+        preset!(oranges),
+        preset!(purples),
+        preset!(reds),
+    ]
+}
+
+/// The preset gradient called `name`.
+pub fn by_name(name: &str) -> Option<Box<dyn Gradient>> {
+    presets()
+        .into_iter()
+        .find(|(_, preset)| *preset == name)
+        .map(|(gradient, _)| gradient)
+}
+
+/// A gradient as saved: the name of a preset, or custom stops.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GradientSpec {
+    /// See `presets()`.
+    Preset(String),
+    /// Colors spread evenly from 0 to 1, in CSS notation such as `#ff8800`.
+    Stops(Vec<String>),
+}
+
+impl GradientSpec {
+    pub fn gradient(&self) -> Result<Box<dyn Gradient>, String> {
+        match self {
+            GradientSpec::Preset(name) => by_name(name).ok_or(format!("unknown gradient {name}")),
+            GradientSpec::Stops(stops) => colorgrad::GradientBuilder::new()
+                .html_colors(stops)
+                .build::<colorgrad::LinearGradient>()
+                .map(|gradient| Box::new(gradient) as Box<dyn Gradient>)
+                .map_err(|error| format!("invalid gradient stops: {error}")),
+        }
+    }
+}
+
+/// Samples `len` colors from `gradient`.
+pub fn palette_colors(gradient: &dyn Gradient, len: usize) -> Vec<[f32; 4]> {
+    gradient
+        .sharp(len as u16, 0.)
+        .colors(len)
+        .iter()
+        .map(|c| [c.r, c.g, c.b, c.a])
+        .collect()
+}
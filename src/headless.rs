@@ -0,0 +1,601 @@
+use crate::coloring::{self, Coloring};
+use crate::fractal::{FractalRenderer, PerturbationFrame, Precision, Scene};
+use crate::perturbation::{ReferenceKey, ReferenceOrbit};
+use crate::readback::read_texture;
+use crate::view::View;
+use eframe::wgpu;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tiff::encoder::colortype::RGBA8;
+use tiff::encoder::{TiffEncoder, TiffKind};
+use tiff::TiffResult;
+
+/// Format of the images rendered without a window, read back as RGBA bytes.
+pub const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// A wgpu device without a window or surface, rendering scenes to memory.
+pub struct Headless {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    info: wgpu::AdapterInfo,
+    data_format: wgpu::TextureFormat,
+}
+
+impl Headless {
+    /// Opens a device on the preferred adapter, or on the fallback (software)
+    /// adapter if `fallback` is set or no other one is available.
+    pub fn new(fallback: bool) -> Result<Headless, String> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let request = |force_fallback_adapter| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::from_env().unwrap_or_default(),
+                force_fallback_adapter,
+                compatible_surface: None,
+            }))
+        };
+        let adapter = match fallback {
+            true => request(true),
+            false => request(false).or_else(|_| request(true)),
+        }
+        .map_err(|error| format!("no wgpu adapter: {error}"))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("headless device"),
+            // The perturbation and histogram passes need more than the
+            // defaults of some adapters, the largest images too.
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))
+        .map_err(|error| format!("no wgpu device: {error}"))?;
+        Ok(Headless {
+            device,
+            queue,
+            info: adapter.get_info(),
+            data_format: coloring::data_format(&adapter),
+        })
+    }
+
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.info
+    }
+
+    /// The largest `max_iterations` rendered as is, see
+    /// `coloring::max_iterations()`.
+    pub fn max_iterations(&self) -> u32 {
+        coloring::max_iterations(self.data_format)
+    }
+
+    /// The largest width and height of an image.
+    pub fn max_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// Renders `scene` into an image of `size`, as rows of RGBA bytes from
+    /// the top.
+    pub fn render(&self, scene: &Scene, size: [u32; 2]) -> Result<Vec<u8>, String> {
+        if size.iter().any(|&v| v == 0 || v > self.max_size()) {
+            return Err(format!(
+                "image size must be between 1 and {} pixels",
+                self.max_size()
+            ));
+        }
+        let mut renderer = self.renderer(scene);
+        let bits = scene.view.bits(size);
+        let (precision, reference) = self.plan(scene, &scene.view, &renderer, size, bits, None);
+        Ok(self.render_tile(
+            &mut renderer,
+            &scene.view,
+            size,
+            precision,
+            reference.as_ref(),
+        ))
+    }
+
+    /// Renders `scene` into an image of `size`, however large, in tiles of
+    /// at most `tile` pixels square. Each strip of tiles is handed to `write`
+    /// as soon as it is done, as rows of RGBA bytes from the top, so that
+    /// only one strip is ever in memory.
+    ///
+    /// `Coloring::Histogram` iterates the tiles twice: first to build the
+    /// distribution of the whole image, shared by all of them.
+    pub fn render_tiled(
+        &self,
+        scene: &Scene,
+        size: [u32; 2],
+        tile: u32,
+        mut write: impl FnMut(&[u8]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        if size.contains(&0) {
+            return Err("image size must be at least 1 pixel".to_owned());
+        }
+        let tile = tile.clamp(1, self.max_size());
+        let mut renderer = self.renderer(scene);
+        // The whole image shares the precision and the reference orbit, so
+        // that tiles match at their seams.
+        let bits = scene.view.bits(size);
+        let (precision, reference) = self.plan(scene, &scene.view, &renderer, size, bits, None);
+        if scene.coloring == Coloring::Histogram && (size[0] > tile || size[1] > tile) {
+            let cdf = self.distribution(
+                &mut renderer,
+                scene,
+                size,
+                tile,
+                precision,
+                reference.as_ref(),
+            );
+            renderer.set_cdf(Some(cdf));
+        }
+        let row_len = size[0] as usize * 4;
+        let mut strip = Vec::new();
+        for y in (0..size[1]).step_by(tile as usize) {
+            let height = tile.min(size[1] - y);
+            strip.resize(row_len * height as usize, 0);
+            for x in (0..size[0]).step_by(tile as usize) {
+                let tile_size = [tile.min(size[0] - x), height];
+                let view = scene.view.tile(size, [x, y], tile_size);
+                let rgba = self.render_tile(
+                    &mut renderer,
+                    &view,
+                    tile_size,
+                    precision,
+                    reference.as_ref(),
+                );
+                let tile_row_len = tile_size[0] as usize * 4;
+                for (row, tile_row) in strip
+                    .chunks_exact_mut(row_len)
+                    .zip(rgba.chunks_exact(tile_row_len))
+                {
+                    let start = x as usize * 4;
+                    row[start..start + tile_row_len].copy_from_slice(tile_row);
+                }
+            }
+            write(&strip)?;
+        }
+        Ok(())
+    }
+
+    /// Renders `frames` images of `size` zooming from the view of `scene` to
+    /// `target`, see `View::zoom_towards()`, handing each one to `write`
+    /// with its index as soon as it is done.
+    pub fn render_animation(
+        &self,
+        scene: &Scene,
+        target: &View,
+        frames: u32,
+        size: [u32; 2],
+        mut write: impl FnMut(u32, &[u8]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        if size.iter().any(|&v| v == 0 || v > self.max_size()) {
+            return Err(format!(
+                "image size must be between 1 and {} pixels",
+                self.max_size()
+            ));
+        }
+        let mut renderer = self.renderer(scene);
+        // Enough for every frame, so that the reference orbit is computed
+        // once when the center does not move.
+        let bits = scene.view.bits(size).max(target.bits(size));
+        let mut previous = None;
+        for frame in 0..frames {
+            let t = match frames {
+                1 => 0.0,
+                _ => f64::from(frame) / f64::from(frames - 1),
+            };
+            let view = scene.view.zoom_towards(target, t, bits);
+            let (precision, reference) =
+                self.plan(scene, &view, &renderer, size, bits, previous.as_ref());
+            let rgba = self.render_tile(&mut renderer, &view, size, precision, reference.as_ref());
+            write(frame, &rgba)?;
+            previous = reference.or(previous);
+        }
+        Ok(())
+    }
+
+    fn renderer(&self, scene: &Scene) -> FractalRenderer {
+        FractalRenderer::for_scene(&self.device, TARGET_FORMAT, self.data_format, scene)
+    }
+
+    /// The precision of an image of `view` and `size`, and the reference
+    /// orbit if it needs one: `previous` if it starts from the center of
+    /// `view` with enough bits, or else a new one computed with `bits`.
+    fn plan(
+        &self,
+        scene: &Scene,
+        view: &View,
+        renderer: &FractalRenderer,
+        size: [u32; 2],
+        bits: usize,
+        previous: Option<&Arc<ReferenceOrbit>>,
+    ) -> (Precision, Option<Arc<ReferenceOrbit>>) {
+        let precision = Precision::for_view(view, size, renderer.supports_perturbation());
+        let key = ReferenceKey {
+            mode: scene.mode,
+            point: view.center.clone(),
+            c: scene.c,
+            max_iterations: scene.max_iterations,
+            bits: view.bits(size),
+        };
+        let reference = (precision == Precision::Perturbation).then(|| match previous {
+            Some(previous) if previous.key.point == key.point && previous.key.bits >= key.bits => {
+                Arc::clone(previous)
+            }
+            // No rush here: compute the reference orbit in place.
+            _ => Arc::new(ReferenceOrbit::new(ReferenceKey {
+                bits: bits.max(key.bits),
+                ..key
+            })),
+        });
+        (precision, reference)
+    }
+
+    /// The distribution of the iteration counts of the image of `scene`, in
+    /// tiles like `render_tiled()`, for `Coloring::Histogram`.
+    fn distribution(
+        &self,
+        renderer: &mut FractalRenderer,
+        scene: &Scene,
+        size: [u32; 2],
+        tile: u32,
+        precision: Precision,
+        reference: Option<&Arc<ReferenceOrbit>>,
+    ) -> Vec<f32> {
+        let max_iterations = scene.max_iterations.min(self.max_iterations());
+        let mut bins = vec![0; max_iterations as usize + 1];
+        for y in (0..size[1]).step_by(tile as usize) {
+            for x in (0..size[0]).step_by(tile as usize) {
+                let tile_size = [tile.min(size[0] - x), tile.min(size[1] - y)];
+                let view = scene.view.tile(size, [x, y], tile_size);
+                self.draw_tile(renderer, &view, tile_size, precision, reference);
+                let data = renderer.read_back(&self.device, &self.queue).data;
+                coloring::count(&data, &mut bins);
+            }
+        }
+        coloring::distribution(&bins)
+    }
+
+    fn render_tile(
+        &self,
+        renderer: &mut FractalRenderer,
+        view: &View,
+        size: [u32; 2],
+        precision: Precision,
+        reference: Option<&Arc<ReferenceOrbit>>,
+    ) -> Vec<u8> {
+        self.draw_tile(renderer, view, size, precision, reference);
+        read_texture(&self.device, &self.queue, renderer.texture())
+    }
+
+    fn draw_tile(
+        &self,
+        renderer: &mut FractalRenderer,
+        view: &View,
+        size: [u32; 2],
+        precision: Precision,
+        reference: Option<&Arc<ReferenceOrbit>>,
+    ) {
+        let perturbation =
+            reference.map(|reference| PerturbationFrame::new(reference.clone(), view, size, true));
+        renderer.prepare(
+            &self.device,
+            &self.queue,
+            size,
+            view,
+            precision,
+            perturbation.as_ref(),
+        );
+        renderer.render(&self.device, &self.queue);
+    }
+}
+
+/// Writes rows of RGBA bytes as an 8-bit PNG file.
+pub fn write_png(path: &Path, size: [u32; 2], rgba: &[u8]) -> Result<(), String> {
+    let file = std::fs::File::create(path)
+        .map_err(|error| format!("cannot create {}: {error}", path.display()))?;
+    png_encoder(file, size)
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(|error| format!("cannot write {}: {error}", path.display()))
+}
+
+fn png_encoder(file: File, size: [u32; 2]) -> png::Encoder<'static, BufWriter<File>> {
+    let mut encoder = png::Encoder::new(BufWriter::new(file), size[0], size[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+}
+
+/// The file formats of `write_image()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Png,
+    /// Uncompressed, BigTIFF past 4 GiB.
+    Tiff,
+}
+
+impl ImageFormat {
+    /// The format named by the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "tif" | "tiff" => Some(ImageFormat::Tiff),
+            _ => None,
+        }
+    }
+}
+
+/// Writes an 8-bit RGBA image of `size` to `path` as it is produced:
+/// `produce` gets a sink for strips of `strip_rows` rows of RGBA bytes from
+/// the top (the last one shorter), such as `Headless::render_tiled()`.
+pub fn write_image(
+    path: &Path,
+    format: ImageFormat,
+    size: [u32; 2],
+    strip_rows: u32,
+    produce: impl FnOnce(&mut dyn FnMut(&[u8]) -> Result<(), String>) -> Result<(), String>,
+) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|error| format!("cannot create {}: {error}", path.display()))?;
+    let written = match format {
+        ImageFormat::Png => write_png_strips(file, size, produce),
+        ImageFormat::Tiff if u64::from(size[0]) * u64::from(size[1]) * 4 < TIFF_MAX_LEN => {
+            write_tiff_strips(TiffEncoder::new(file), size, strip_rows, produce)
+        }
+        ImageFormat::Tiff => {
+            write_tiff_strips(TiffEncoder::new_big(file), size, strip_rows, produce)
+        }
+    };
+    written.map_err(|error| format!("cannot write {}: {error}", path.display()))
+}
+
+/// Leaves room for the directory below the 4 GiB offsets of plain TIFF.
+const TIFF_MAX_LEN: u64 = (1 << 32) - (1 << 20);
+
+fn write_png_strips(
+    file: File,
+    size: [u32; 2],
+    produce: impl FnOnce(&mut dyn FnMut(&[u8]) -> Result<(), String>) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut writer = png_encoder(file, size)
+        .write_header()
+        .and_then(|writer| writer.into_stream_writer())
+        .map_err(|error| error.to_string())?;
+    produce(&mut |rows| writer.write_all(rows).map_err(|error| error.to_string()))?;
+    writer.finish().map_err(|error| error.to_string())
+}
+
+fn write_tiff_strips<K: TiffKind>(
+    encoder: TiffResult<TiffEncoder<File, K>>,
+    size: [u32; 2],
+    strip_rows: u32,
+    produce: impl FnOnce(&mut dyn FnMut(&[u8]) -> Result<(), String>) -> Result<(), String>,
+) -> Result<(), String> {
+    // Uncompressed: the encoder only compresses whole images, not strips.
+    let mut encoder = encoder.map_err(|error| error.to_string())?;
+    let mut image = encoder
+        .new_image::<RGBA8>(size[0], size[1])
+        .and_then(|mut image| image.rows_per_strip(strip_rows).map(|()| image))
+        .map_err(|error| error.to_string())?;
+    produce(&mut |rows| image.write_strip(rows).map_err(|error| error.to_string()))?;
+    image.finish().map_err(|error| error.to_string())
+}
+
+/// The file formats of `AnimationWriter`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AnimationFormat {
+    /// One PNG file per frame, numbered in place of `{}` in the path.
+    Sequence,
+    /// An animated PNG.
+    Apng,
+    /// An animated GIF, quantized to 256 colors per frame.
+    Gif,
+}
+
+impl AnimationFormat {
+    /// The format named by `path`: a sequence if it holds `{}`, or else by
+    /// its extension.
+    pub fn from_path(path: &Path) -> Option<AnimationFormat> {
+        if path.to_str()?.contains(FRAME_NUMBER) {
+            return Some(AnimationFormat::Sequence);
+        }
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            "gif" => Some(AnimationFormat::Gif),
+            _ => None,
+        }
+    }
+}
+
+/// Stands for the frame number in the paths of `AnimationFormat::Sequence`.
+const FRAME_NUMBER: &str = "{}";
+
+/// NeuQuant sampling of GIF frames, from 1 (best) to 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+/// Writes the frames of an animation one at a time.
+pub struct AnimationWriter {
+    size: [u32; 2],
+    encoder: AnimationEncoder,
+}
+
+enum AnimationEncoder {
+    Sequence { path: String, digits: usize },
+    Apng(png::Writer<BufWriter<File>>),
+    Gif(gif::Encoder<BufWriter<File>>, u16),
+}
+
+impl AnimationWriter {
+    /// Starts an animation of `frames` frames of `size`, shown for `delay`
+    /// milliseconds each and looping forever.
+    pub fn new(
+        path: &Path,
+        format: AnimationFormat,
+        size: [u32; 2],
+        frames: u32,
+        delay: u16,
+    ) -> Result<AnimationWriter, String> {
+        let create = || {
+            File::create(path).map_err(|error| format!("cannot create {}: {error}", path.display()))
+        };
+        let failed =
+            |error: &dyn std::fmt::Display| format!("cannot write {}: {error}", path.display());
+        let encoder = match format {
+            AnimationFormat::Sequence => AnimationEncoder::Sequence {
+                path: path.to_string_lossy().into_owned(),
+                digits: (frames.max(2) - 1).to_string().len(),
+            },
+            AnimationFormat::Apng => {
+                let mut encoder = png_encoder(create()?, size);
+                encoder
+                    .set_animated(frames, 0)
+                    .and_then(|()| encoder.set_frame_delay(delay, 1000))
+                    .and_then(|()| encoder.set_dispose_op(png::DisposeOp::Background))
+                    .map_err(|error| failed(&error))?;
+                AnimationEncoder::Apng(encoder.write_header().map_err(|error| failed(&error))?)
+            }
+            AnimationFormat::Gif => {
+                let [width, height] = size.map(u16::try_from);
+                let (Ok(width), Ok(height)) = (width, height) else {
+                    return Err(format!("GIF images are at most {} pixels wide", u16::MAX));
+                };
+                let mut encoder = gif::Encoder::new(BufWriter::new(create()?), width, height, &[])
+                    .map_err(|error| failed(&error))?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|error| failed(&error))?;
+                // GIF delays are in hundredths of a second.
+                AnimationEncoder::Gif(encoder, delay.div_ceil(10))
+            }
+        };
+        Ok(AnimationWriter { size, encoder })
+    }
+
+    /// Writes frame `index`, rows of RGBA bytes from the top. Frames come
+    /// in order.
+    pub fn write_frame(&mut self, index: u32, rgba: &[u8]) -> Result<(), String> {
+        match &mut self.encoder {
+            AnimationEncoder::Sequence { path, digits } => {
+                let path =
+                    path.replace(FRAME_NUMBER, &format!("{index:0digits$}", digits = *digits));
+                write_png(Path::new(&path), self.size, rgba)
+            }
+            AnimationEncoder::Apng(writer) => writer
+                .write_image_data(rgba)
+                .map_err(|error| format!("cannot write frame {index}: {error}")),
+            AnimationEncoder::Gif(encoder, delay) => {
+                let [width, height] = self.size.map(|v| v as u16);
+                let mut frame =
+                    gif::Frame::from_rgba_speed(width, height, &mut rgba.to_vec(), GIF_SPEED);
+                frame.delay = *delay;
+                frame.dispose = gif::DisposalMethod::Background;
+                encoder
+                    .write_frame(&frame)
+                    .map_err(|error| format!("cannot write frame {index}: {error}"))
+            }
+        }
+    }
+
+    /// Completes the file, once all the frames are written.
+    pub fn finish(self) -> Result<(), String> {
+        match self.encoder {
+            AnimationEncoder::Sequence { .. } => Ok(()),
+            AnimationEncoder::Apng(writer) => writer.finish().map_err(|error| error.to_string()),
+            AnimationEncoder::Gif(encoder, _) => {
+                let mut writer = encoder.into_inner().map_err(|error| error.to_string())?;
+                writer.flush().map_err(|error| error.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coloring::{Interior, Palette};
+    use crate::fractal::Mode;
+    use crate::trap::Trap;
+    use crate::view::parse_real;
+
+    const SIZE: [u32; 2] = [96, 64];
+
+    fn scene(coloring: Coloring) -> Scene {
+        let mode = Mode::Mandelbrot;
+        Scene {
+            mode,
+            view: View::home(mode.formula(), 1.5),
+            c: [0.0; 2],
+            max_iterations: 256,
+            escape_radius: 2.0,
+            coloring,
+            palette: Palette::new((0..16).map(|i| [i as f32 / 15.0, 0.5, 0.0, 1.0]).collect()),
+            boundary: 0.0,
+            interior: Interior::Black,
+            interior_colors: vec![[0.0, 0.0, 0.0, 1.0]],
+            trap: Trap::default(),
+        }
+    }
+
+    fn render_tiled(headless: &Headless, scene: &Scene, tile: u32) -> Vec<u8> {
+        let mut rgba = Vec::new();
+        headless
+            .render_tiled(scene, SIZE, tile, |strip| {
+                rgba.extend_from_slice(strip);
+                Ok(())
+            })
+            .unwrap();
+        rgba
+    }
+
+    /// Tiles match the image rendered at once, `Coloring::Histogram` too.
+    #[test]
+    fn tiles_match_the_whole_image() {
+        let Ok(headless) = Headless::new(false) else {
+            eprintln!("no wgpu adapter, skipping");
+            return;
+        };
+        for coloring in [Coloring::Banded, Coloring::Histogram] {
+            let scene = scene(coloring);
+            let whole = headless.render(&scene, SIZE).unwrap();
+            let tiled = render_tiled(&headless, &scene, 40);
+            let differences = whole.iter().zip(&tiled).filter(|(a, b)| a != b).count();
+            assert!(
+                differences <= whole.len() / 1000,
+                "{coloring:?}: {differences}"
+            );
+        }
+    }
+
+    /// Zooming into a fixed center computes one reference orbit.
+    #[test]
+    fn animation_reuses_the_reference() {
+        let Ok(headless) = Headless::new(false) else {
+            eprintln!("no wgpu adapter, skipping");
+            return;
+        };
+        let mut scene = scene(Coloring::Banded);
+        scene.view = View {
+            center: ["-1.7548776662466927", "0"].map(|v| parse_real(v, 0).unwrap()),
+            zoom: 60.0,
+        };
+        let target = View {
+            zoom: 70.0,
+            ..scene.view.clone()
+        };
+        let renderer = headless.renderer(&scene);
+        let bits = scene.view.bits(SIZE).max(target.bits(SIZE));
+        let plan = |t: f64, previous: Option<&Arc<ReferenceOrbit>>| {
+            let view = scene.view.zoom_towards(&target, t, bits);
+            headless.plan(&scene, &view, &renderer, SIZE, bits, previous)
+        };
+        let (precision, first) = plan(0.0, None);
+        if precision != Precision::Perturbation {
+            eprintln!("no perturbation, skipping");
+            return;
+        }
+        let first = first.unwrap();
+        let (_, last) = plan(1.0, Some(&first));
+        assert!(Arc::ptr_eq(&first, &last.unwrap()));
+    }
+}
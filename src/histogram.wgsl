@@ -0,0 +1,68 @@
+// Histogram equalization: counts the escaped pixels of the data texture per
+// iteration count, then turns the counts into the cumulative distribution read
+// by `fs_color`.
+
+const SCAN_SIZE: u32 = 256u;
+
+@group(0) @binding(0)
+var data: texture_2d<f32>;
+
+// Number of escaped pixels per iteration count.
+@group(0) @binding(1)
+var<storage, read_write> bins: array<atomic<u32>>;
+
+// Fraction of the escaped pixels with at most as many iterations, per
+// iteration count.
+@group(0) @binding(2)
+var<storage, read_write> cdf: array<f32>;
+
+var<workgroup> partial: array<u32, SCAN_SIZE>;
+var<workgroup> total: u32;
+
+@compute @workgroup_size(16, 16)
+fn cs_count(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= textureDimensions(data))) {
+        return;
+    }
+    let iterations = textureLoad(data, id.xy, 0).x;
+    if (iterations < 1.0) {
+        return;
+    }
+    let bin = min(u32(iterations), arrayLength(&bins) - 1u);
+    atomicAdd(&bins[bin], 1u);
+}
+
+// A single workgroup: each invocation sums a chunk of bins, the chunk sums are
+// scanned, then each invocation writes the distribution over its chunk.
+@compute @workgroup_size(256)
+fn cs_scan(@builtin(local_invocation_index) index: u32) {
+    let len = arrayLength(&bins);
+    let chunk = (len + SCAN_SIZE - 1u) / SCAN_SIZE;
+    let start = min(index * chunk, len);
+    let end = min(start + chunk, len);
+
+    var sum = 0u;
+    for (var i = start; i < end; i++) {
+        sum += atomicLoad(&bins[i]);
+    }
+    partial[index] = sum;
+    workgroupBarrier();
+
+    if (index == 0u) {
+        var prefix = 0u;
+        for (var i = 0u; i < SCAN_SIZE; i++) {
+            let chunk_sum = partial[i];
+            partial[i] = prefix;
+            prefix += chunk_sum;
+        }
+        total = prefix;
+    }
+    workgroupBarrier();
+
+    let count = f32(max(total, 1u));
+    var cumulative = partial[index];
+    for (var i = start; i < end; i++) {
+        cumulative += atomicLoad(&bins[i]);
+        cdf[i] = f32(cumulative) / count;
+    }
+}
@@ -0,0 +1,160 @@
+use crate::fractal::Mode;
+use crate::session::PaletteSettings;
+use crate::view::View;
+
+/// Number of states kept by a `History`, the oldest ones are dropped.
+pub const MAX_HISTORY: usize = 100;
+
+/// What undo and redo go back and forth between: where the plot is and how it
+/// is colored.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HistoryState {
+    pub mode: Mode,
+    pub view: View,
+    pub c: [f32; 2],
+    pub max_iterations: u32,
+    pub palette: PaletteSettings,
+}
+
+/// The states gone through, oldest first, and the one shown: those before it
+/// are undone in turn and those after it redone.
+pub struct History<T> {
+    entries: Vec<T>,
+    current: usize,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        History {
+            entries: Vec::new(),
+            current: 0,
+        }
+    }
+}
+
+impl<T> History<T> {
+    pub fn entries(&self) -> &[T] {
+        &self.entries
+    }
+
+    pub fn entries_mut(&mut self) -> &mut [T] {
+        &mut self.entries
+    }
+
+    /// The index of the entry shown, if any.
+    pub fn current_index(&self) -> Option<usize> {
+        (!self.entries.is_empty()).then_some(self.current)
+    }
+
+    pub fn current(&self) -> Option<&T> {
+        self.entries.get(self.current)
+    }
+
+    /// Adds `entry` after the current one, which it becomes, and returns the
+    /// entries dropped: those that could be redone, and the oldest ones past
+    /// `MAX_HISTORY`.
+    pub fn push(&mut self, entry: T) -> Vec<T> {
+        let mut dropped = match self.entries.is_empty() {
+            true => Vec::new(),
+            false => self.entries.split_off(self.current + 1),
+        };
+        self.entries.push(entry);
+        let excess = self.entries.len().saturating_sub(MAX_HISTORY);
+        dropped.extend(self.entries.drain(..excess));
+        self.current = self.entries.len() - 1;
+        dropped
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    /// Steps back, returning the entry to show.
+    pub fn undo(&mut self) -> Option<&T> {
+        self.can_undo().then(|| {
+            self.current -= 1;
+            &self.entries[self.current]
+        })
+    }
+
+    /// Steps forward, returning the entry to show.
+    pub fn redo(&mut self) -> Option<&T> {
+        self.can_redo().then(|| {
+            self.current += 1;
+            &self.entries[self.current]
+        })
+    }
+
+    /// Jumps to the entry at `index`, keeping the others to undo and redo.
+    pub fn go_to(&mut self, index: usize) -> Option<&T> {
+        let entry = self.entries.get(index)?;
+        self.current = index;
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: impl IntoIterator<Item = usize>) -> History<usize> {
+        let mut history = History::default();
+        for entry in entries {
+            assert!(history.push(entry).is_empty());
+        }
+        history
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut history = history([]);
+        assert_eq!(history.current_index(), None);
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.redo(), None);
+
+        let mut history = self::history(0..3);
+        assert_eq!(history.current(), Some(&2));
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.undo(), Some(&1));
+        assert_eq!(history.undo(), Some(&0));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.current_index(), Some(0));
+        assert_eq!(history.redo(), Some(&1));
+        assert_eq!(history.go_to(2), Some(&2));
+        assert_eq!(history.go_to(3), None);
+        assert_eq!(history.current_index(), Some(2));
+        assert_eq!(history.entries(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn push_drops_the_redo_entries() {
+        let mut history = history(0..4);
+        history.undo();
+        history.undo();
+        assert_eq!(history.push(10), [2, 3]);
+        assert_eq!(history.entries(), [0, 1, 10]);
+        assert_eq!(history.current(), Some(&10));
+        assert!(!history.can_redo());
+        history.go_to(0);
+        assert_eq!(history.push(20), [1, 10]);
+        assert_eq!(history.entries(), [0, 20]);
+    }
+
+    #[test]
+    fn push_drops_the_oldest_entries() {
+        let mut history = history(0..MAX_HISTORY);
+        assert_eq!(history.push(MAX_HISTORY), [0]);
+        assert_eq!(history.entries().len(), MAX_HISTORY);
+        assert_eq!(history.entries()[0], 1);
+        assert_eq!(history.current_index(), Some(MAX_HISTORY - 1));
+        history.undo();
+        assert_eq!(history.push(0), [MAX_HISTORY]);
+        assert_eq!(history.push(1), [1]);
+        assert_eq!(history.entries().len(), MAX_HISTORY);
+        assert_eq!(history.current(), Some(&1));
+    }
+}
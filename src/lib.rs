@@ -0,0 +1,17 @@
+pub mod bookmark;
+pub mod coloring;
+pub mod cpu;
+pub mod fractal;
+pub mod gradient;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod headless;
+pub mod history;
+pub mod link;
+pub mod params;
+pub mod perturbation;
+pub mod readback;
+pub mod session;
+pub mod thumbnail;
+pub mod trap;
+pub mod view;
+pub mod wgsl_struct;
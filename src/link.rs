@@ -0,0 +1,194 @@
+use crate::fractal::Mode;
+use crate::gradient::GradientSpec;
+use crate::params::finite;
+use crate::view::{parse_real, View, MAX_ZOOM, MIN_ZOOM};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::collections::HashMap;
+
+/// Version of the fragment written by this build, see `SESSION_VERSION`.
+pub const LINK_VERSION: u32 = 1;
+
+/// What is percent-encoded in a fragment: what URLs do not allow there.
+const FRAGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'`');
+
+/// What is percent-encoded in a value: also the separators of the fragment,
+/// so a value never splits where it should not.
+const VALUE: &AsciiSet = &FRAGMENT.add(b'&').add(b'=').add(b',');
+
+/// A location shared as the fragment of the page URL, in the web build:
+///
+/// ```text
+/// #v=1&mode=burning-ship-julia&re=0&im=0&zoom=-1.5&c=-0.8,0.156&iterations=1024&gradient=viridis
+/// ```
+///
+/// `gradient` is a preset, or custom stops separated by commas, like
+/// `%23000000,rgb(255%2C136%2C0),white`: commas within a stop are encoded.
+/// Fields are split before their values are decoded.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Link {
+    pub mode: Mode,
+    pub view: View,
+    pub c: [f32; 2],
+    pub max_iterations: u32,
+    pub gradient: GradientSpec,
+}
+
+impl Link {
+    /// The fragment, percent-encoded and without the `#`.
+    pub fn to_fragment(&self) -> String {
+        let [re, im] = self.view.center_text();
+        let encode = |value: &str| utf8_percent_encode(value, VALUE).to_string();
+        let gradient = match &self.gradient {
+            GradientSpec::Preset(name) => encode(name),
+            GradientSpec::Stops(stops) => stops
+                .iter()
+                .map(|stop| encode(stop))
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        let fields = [
+            ("v", LINK_VERSION.to_string()),
+            ("mode", encode(&self.mode.slug())),
+            ("re", encode(&re)),
+            ("im", encode(&im)),
+            ("zoom", self.view.zoom.to_string()),
+            ("c", format!("{},{}", self.c[0], self.c[1])),
+            ("iterations", self.max_iterations.to_string()),
+            ("gradient", gradient),
+        ];
+        fields
+            .map(|(key, value)| format!("{key}={value}"))
+            .join("&")
+    }
+
+    /// Parses a percent-encoded fragment, with or without the `#`.
+    /// The zoom is clamped to `MIN_ZOOM..=MAX_ZOOM`, as links are followed
+    /// without asking.
+    pub fn from_fragment(fragment: &str) -> Result<Link, String> {
+        let fragment = fragment.strip_prefix('#').unwrap_or(fragment);
+        let decode = |value: &str| percent_decode_str(value).decode_utf8_lossy().into_owned();
+        let fields: HashMap<&str, &str> = fragment
+            .split('&')
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let raw = |key: &str| {
+            fields
+                .get(key)
+                .copied()
+                .ok_or(format!("the link has no {key}"))
+        };
+        let field = |key: &str| raw(key).map(decode);
+
+        let version: u32 = number(&field("v")?)?;
+        if version != LINK_VERSION {
+            return Err(format!("unknown link version {version}"));
+        }
+        let mode = field("mode")?;
+        let mode = Mode::from_name(&mode).ok_or(format!("unknown mode {mode}"))?;
+        let [re, im] = ["re", "im"].map(|key| {
+            let text = field(key)?;
+            parse_real(&text, 0).ok_or(format!("invalid coordinate {text}"))
+        });
+        let zoom: f64 = number(&field("zoom")?)?;
+        if !zoom.is_finite() {
+            return Err(format!("the zoom is {zoom}, not a finite number"));
+        }
+        let view = View {
+            center: [re?, im?],
+            zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+        };
+        let c = raw("c")?;
+        let (re, im) = c.split_once(',').ok_or(format!(
+            "expected c as two numbers separated by a comma: {}",
+            decode(c)
+        ))?;
+        let c = [number(&decode(re))?, number(&decode(im))?];
+        c.into_iter().try_for_each(|v| finite("c", v))?;
+        let gradient = raw("gradient")?;
+        let gradient = if gradient.contains(',') {
+            GradientSpec::Stops(gradient.split(',').map(decode).collect())
+        } else {
+            GradientSpec::Preset(decode(gradient))
+        };
+        Ok(Link {
+            mode,
+            view,
+            c,
+            max_iterations: number(&field("iterations")?)?,
+            gradient,
+        })
+    }
+}
+
+fn number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("invalid number {text}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(gradient: GradientSpec) -> Link {
+        Link {
+            mode: Mode::BurningShipJulia,
+            view: View {
+                center: ["-1.75", "0.0625"].map(|v| parse_real(v, 0).unwrap()),
+                zoom: 20.25,
+            },
+            c: [-0.8, 0.156],
+            max_iterations: 5000,
+            gradient,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let stops = |stops: [&str; 3]| GradientSpec::Stops(stops.map(str::to_owned).to_vec());
+        for gradient in [
+            GradientSpec::Preset("viridis".to_owned()),
+            stops(["#000000", "#ff8800", "white"]),
+            stops(["rgb(1,2,3)", "#ff8800", "a&b=c%"]),
+        ] {
+            let link = link(gradient);
+            let fragment = link.to_fragment();
+            assert!(!fragment.contains('#'), "{fragment}");
+            assert_eq!(Link::from_fragment(&fragment), Ok(link.clone()));
+            assert_eq!(Link::from_fragment(&format!("#{fragment}")), Ok(link));
+        }
+    }
+
+    #[test]
+    fn clamps_the_zoom() {
+        let fragment = link(GradientSpec::Preset("viridis".to_owned())).to_fragment();
+        for (zoom, clamped) in [("1e300", MAX_ZOOM), ("-1e300", MIN_ZOOM)] {
+            let fragment = fragment.replace("zoom=20.25", &format!("zoom={zoom}"));
+            assert_eq!(Link::from_fragment(&fragment).unwrap().view.zoom, clamped);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        let fragment = link(GradientSpec::Preset("viridis".to_owned())).to_fragment();
+        for (from, to) in [
+            ("zoom=20.25", "zoom=NaN"),
+            ("zoom=20.25", "zoom=inf"),
+            ("c=-0.8", "c=inf"),
+            ("0.156", "NaN"),
+            ("v=1", "v=2"),
+            ("mode=burning-ship-julia", "mode=nope"),
+        ] {
+            assert!(fragment.contains(from), "{fragment}");
+            let fragment = fragment.replace(from, to);
+            assert!(Link::from_fragment(&fragment).is_err(), "{fragment}");
+        }
+    }
+}
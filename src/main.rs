@@ -1,10 +1,3 @@
-mod julia;
-mod mandelbrot;
-mod wgsl_struct;
-
-use crate::julia::JuliaRenderUtils;
-use crate::mandelbrot::MandelbrotRenderUtils;
-use crate::wgsl_struct::Vertex;
 use colorgrad::Gradient;
 use eframe::egui::Rect;
 use eframe::egui_wgpu::{WgpuSetup, WgpuSetupCreateNew};
@@ -20,11 +13,56 @@ use eframe::{
     epaint::{self},
     wgpu, App, AppCreator, CreationContext, Frame,
 };
-use egui_plot::{Legend, PlotBounds, PlotImage};
+use egui_plot::{Legend, Line, PlotImage, PlotMemory, PlotPoint, Points};
+use fractal_egui_demo::bookmark::{Bookmark, BookmarkCollection};
+use fractal_egui_demo::coloring::{
+    self, Coloring, Filter, Interior, Palette, INTERIOR_LEN, MAX_BOUNDARY,
+};
+use fractal_egui_demo::cpu::{self, CpuColoring, CpuParams, CpuRenderer};
+use fractal_egui_demo::fractal::{
+    FractalCallback, FractalRenderer, FractalRenderers, Mode, PerturbationFrame, Plane, Precision,
+    Scene, MAX_ESCAPE_RADIUS, MAX_ITERATIONS,
+};
+use fractal_egui_demo::gradient::{
+    self, palette_colors, GradientSpec, MAX_PALETTE_LEN, PALETTE_LEN,
+};
+use fractal_egui_demo::history::{History, HistoryState};
+#[cfg(target_arch = "wasm32")]
+use fractal_egui_demo::link::Link;
+use fractal_egui_demo::params::{ColoringSettings, Params, ParamsFormat, PARAMS_VERSION};
+use fractal_egui_demo::perturbation::{ReferenceKey, References};
+use fractal_egui_demo::readback::PixelData;
+use fractal_egui_demo::session::{PaletteSettings, Session, SESSION_VERSION};
+use fractal_egui_demo::thumbnail::{Thumbnailer, THUMBNAIL_SIZE};
+use fractal_egui_demo::trap::{Trap, TrapShape, MAX_TRAP_RADIUS};
+use fractal_egui_demo::view::{format_real, parse_real, plot_pixel, View, MAX_ZOOM, MIN_ZOOM};
 use std::collections::HashMap;
 use std::sync::Arc;
 
-const COLOR_NUM: usize = 128;
+/// Key of the default interior gradient in `gradient_map`, viridis.
+const INTERIOR_GRADIENT: usize = 7;
+/// Key of the gradient of custom stops in `gradient_map`, once there is one.
+const CUSTOM_GRADIENT: usize = KEYS.len();
+/// Least time between two updates of the page URL, in seconds.
+#[cfg(target_arch = "wasm32")]
+const LINK_INTERVAL: f64 = 0.5;
+/// How long a state is left alone before it goes into the undo history, in
+/// seconds, so that a zoom with the wheel is a single step.
+const HISTORY_DELAY: f64 = 0.5;
+const UNDO: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+const REDO: egui::KeyboardShortcut = egui::KeyboardShortcut::new(
+    egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT),
+    egui::Key::Z,
+);
+const REDO_Y: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
+/// How far an arrow key pans, in view heights.
+const KEY_PAN: f64 = 0.1;
+/// How much + and - zoom, in powers of 2.
+const KEY_ZOOM: f64 = 0.5;
+/// Least side of a box zoom rectangle, in points, under which it is a click.
+const MIN_BOX: f32 = 4.0;
 // const KEYS: [i32; 38] = [
 //     0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
 //     26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37,
@@ -47,37 +85,125 @@ const fn calculate_array<const N: usize>() -> [usize; N] {
 }
 
 // static mut SELECTED: i32 =1;
-const MAX_ITERATIONS: u32 = 65536;
+/// Most points of an orbit drawn.
+const ORBIT_LEN: usize = 4096;
+const ORBIT_COLOR: egui::Color32 = egui::Color32::YELLOW;
 
-#[derive(PartialEq, Clone)]
-enum Mode {
-    Mandelbrot,
-    Julia,
+/// A bookmark and its thumbnail, once rendered.
+struct BookmarkEntry {
+    bookmark: Bookmark,
+    thumbnail: Option<epaint::TextureId>,
+}
+
+/// A state of the undo history and its thumbnail, once rendered.
+struct HistoryEntry {
+    state: HistoryState,
+    thumbnail: Option<epaint::TextureId>,
 }
 
 pub struct MyApp {
     show_cpu: bool,
     show_gpu: bool,
-    dirty: bool,
-    mandelbrot_texture_id: epaint::TextureId,
-    julia_texture_id: epaint::TextureId,
-    mandelbrot_points: Arc<Vec<Vertex>>,
-    julia_points: Arc<Vec<Vertex>>,
+    cpu: CpuRenderer,
+    palette: Palette,
+    /// Number of palette entries sampled from the gradient.
+    palette_len: usize,
+    texture_ids: HashMap<Mode, epaint::TextureId>,
+    views: HashMap<Mode, View>,
+    /// The center of the view as typed in the toolbar.
+    center_text: [String; 2],
+    /// Size of the plot area in the last frame.
+    dimensions: [u32; 2],
     last_selected: usize,
     selected: usize,
     // text_map: HashMap<i32, String>,
     // gradient_map: HashMap<String, dyn Fn() -> Gradient>,
     gradient_map: HashMap<usize, (Box<dyn Gradient>, &'static str)>,
     max_iterations: u32,
+    /// `MAX_ITERATIONS`, or less when the data texture cannot hold as many,
+    /// see `coloring::max_iterations()`.
+    iteration_limit: u32,
     mode: Mode,
     last_mode: Mode,
     // show_mandelbrot: bool,
     // show_julia: bool,
     c: [f32; 2],
+    escape_radius: f32,
+    coloring: Coloring,
+    /// Width of the darkened exterior along the boundary, in pixels.
+    boundary: f32,
+    interior: Interior,
+    /// Keys of the interior gradient in `gradient_map`, and its colors.
+    interior_selected: usize,
+    last_interior_selected: usize,
+    interior_colors: Vec<[f32; 4]>,
+    /// The stops of a custom interior gradient, until a preset is picked.
+    interior_stops: Option<Vec<String>>,
+    /// The stops of the `CUSTOM_GRADIENT`.
+    custom_stops: Vec<String>,
+    trap: Trap,
+    /// Whether the trap is drawn over the plot.
+    show_trap: bool,
+    /// Whether the `Mode::dual()` of the mode are plotted side by side, the
+    /// first one picking the `c` of the second.
+    dual_view: bool,
+    /// Whether the orbit of the pinned or hovered point is drawn over its
+    /// plot and plotted in the side panel.
+    show_orbit: bool,
+    /// The point under the pointer, and the one pinned with a right click,
+    /// with the mode of their plot.
+    hovered: Option<(Mode, [f64; 2])>,
+    pinned: Option<(Mode, [f64; 2])>,
+    precision: Precision,
+    /// One per mode, so that the plots of the dual view keep their own.
+    references: HashMap<Mode, References>,
+    perturbation_supported: bool,
+    histogram_supported: bool,
+    use_series: bool,
+    show_bookmarks: bool,
+    bookmarks: Vec<BookmarkEntry>,
+    /// The name of the next bookmark, and the filter of the list.
+    bookmark_name: String,
+    bookmark_search: String,
+    /// The bookmark being renamed, and its new name.
+    renaming: Option<(usize, String)>,
+    /// The JSON of the import/export box, and how the last import or export
+    /// went.
+    bookmark_json: String,
+    bookmark_status: String,
+    /// The file bookmarks are imported from and exported to.
+    #[cfg(not(target_arch = "wasm32"))]
+    bookmark_path: String,
+    thumbnailer: Thumbnailer,
+    /// Whether dragging draws a rectangle to zoom into instead of panning.
+    box_zoom: bool,
+    /// The plot a rectangle is dragged on and where it started.
+    box_start: Option<(Mode, egui::Pos2)>,
+    /// Whether the plot widgets are sized as squares. Their axes have the
+    /// same scale either way, as pixels of a `View` are square.
+    square_plot: bool,
+    show_history: bool,
+    history: History<HistoryEntry>,
+    /// A state not in the history yet, and since when it has been shown.
+    history_candidate: Option<(HistoryState, f64)>,
+    /// The TOML of the parameter box, and how the last copy or load went.
+    params_text: String,
+    params_status: String,
+    /// The parameter file saved and loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    params_path: String,
+    /// The fragment of the page URL last read, and the one last written
+    /// with the time it was, see `sync_link()`.
+    #[cfg(target_arch = "wasm32")]
+    link_hash: String,
+    #[cfg(target_arch = "wasm32")]
+    link_fragment: String,
+    #[cfg(target_arch = "wasm32")]
+    link_time: f64,
 }
 
 impl MyApp {
-    pub fn new<'a>(cc: &'a CreationContext<'a>, palette: [[f32; 4]; COLOR_NUM]) -> Option<Self> {
+    pub fn new<'a>(cc: &'a CreationContext<'a>, palette: Palette) -> Option<Self> {
         // let wgpu_render_state = cc.wgpu_render_state.as_ref()?;
         let wgpu_render_state = cc
             .wgpu_render_state
@@ -86,40 +212,43 @@ impl MyApp {
 
         let device = &wgpu_render_state.device;
         let target_format = wgpu_render_state.target_format;
+        let data_format = coloring::data_format(&wgpu_render_state.adapter);
+        let iteration_limit = MAX_ITERATIONS.min(coloring::max_iterations(data_format));
 
         // let target_format = wgpu::TextureFormat::Rgba32Float;
 
-        let mandelbrot_util =
-            MandelbrotRenderUtils::new(device, target_format, palette, MAX_ITERATIONS);
-        let mandelbrot_texture_id = {
-            let mut renderer = wgpu_render_state.renderer.write();
-            renderer.register_native_texture(
+        let mut renderers = FractalRenderers::default();
+        let mut texture_ids = HashMap::new();
+        let mut views = HashMap::new();
+        let mut perturbation_supported = true;
+        let mut histogram_supported = true;
+        for mode in Mode::ALL {
+            let util = FractalRenderer::new(
                 device,
-                &mandelbrot_util.create_view(),
-                wgpu::FilterMode::Linear,
-            )
-        };
-
-        wgpu_render_state
-            .renderer
-            .write()
-            .callback_resources
-            .insert(mandelbrot_util);
-
-        let julia_util = JuliaRenderUtils::new(device, target_format, palette, MAX_ITERATIONS);
-        let julia_texture_id = {
-            let mut renderer = wgpu_render_state.renderer.write();
-            renderer.register_native_texture(
+                target_format,
+                data_format,
+                mode.formula(),
+                palette.clone(),
+                iteration_limit,
+            );
+            let texture_id = wgpu_render_state.renderer.write().register_native_texture(
                 device,
-                &julia_util.create_view(),
+                &util.create_view(),
                 wgpu::FilterMode::Linear,
-            )
-        };
+            );
+            if mode.formula().supports_perturbation() {
+                perturbation_supported &= util.supports_perturbation();
+            }
+            histogram_supported &= util.supports_histogram();
+            renderers.insert(mode, util);
+            texture_ids.insert(mode, texture_id);
+            views.insert(mode, View::home(mode.formula(), 1.0));
+        }
         wgpu_render_state
             .renderer
             .write()
             .callback_resources
-            .insert(julia_util);
+            .insert(renderers);
 
         // let presets = [
         //     colorgrad::cubehelix_default,
@@ -163,56 +292,7 @@ impl MyApp {
         //     colorgrad::yl_or_rd,
         // ];
 
-        macro_rules! preset {
-            ($name:ident) => {
-                (Box::new(colorgrad::preset::$name()), stringify!($name))
-            };
-        }
-
-        pub fn preset() -> Vec<(Box<dyn Gradient>, &'static str)> {
-            vec![
-                preset!(sinebow),
-                preset!(turbo),
-                preset!(cividis),
-                preset!(rainbow),
-                preset!(cubehelix_default),
-                preset!(warm),
-                preset!(cool),
-                preset!(viridis),
-                preset!(inferno),
-                preset!(magma),
-                preset!(plasma),
-                preset!(bu_gn),
-                preset!(bu_pu),
-                preset!(gn_bu),
-                preset!(or_rd),
-                preset!(pu_bu_gn),
-                preset!(pu_bu),
-                preset!(pu_rd),
-                preset!(rd_pu),
-                preset!(yl_gn_bu),
-                preset!(yl_gn),
-                preset!(yl_or_br),
-                preset!(yl_or_rd),
-                preset!(br_bg),
-                preset!(pr_gn),
-                preset!(pi_yg),
-                preset!(pu_or),
-                preset!(rd_bu),
-                preset!(rd_gy),
-                preset!(rd_yl_bu),
-                preset!(rd_yl_gn),
-                preset!(spectral),
-                preset!(blues),
-                preset!(greens),
-                preset!(greys),
-                preset!(oranges),
-                preset!(purples),
-                preset!(reds),
-            ]
-        }
-
-        let presets = preset();
+        let presets = gradient::presets();
         let gradient_map: HashMap<usize, (Box<dyn Gradient>, &'static str)> = {
             let mut map: HashMap<usize, (Box<dyn Gradient>, &'static str)> = HashMap::new();
             for i in 0..KEYS.len() {
@@ -279,92 +359,1478 @@ impl MyApp {
         //     }
         //     map
         // };
+        let interior_colors = palette_colors(&*gradient_map[&INTERIOR_GRADIENT].0, INTERIOR_LEN);
 
-        Some(Self {
+        let mut app = Self {
             show_cpu: false,
             show_gpu: true,
-            dirty: true,
-            mandelbrot_texture_id,
-            julia_texture_id,
-            mandelbrot_points: Arc::new(mandelbrot_vertices()),
-            julia_points: Arc::new(julia_vertices()),
+            cpu: CpuRenderer::default(),
+            palette_len: palette.colors.len(),
+            palette,
+            texture_ids,
+            views,
+            center_text: Default::default(),
+            dimensions: [1, 1],
             last_selected: 4,
             selected: 4,
             // text_map,
             gradient_map,
-            max_iterations: MAX_ITERATIONS,
+            max_iterations: iteration_limit,
+            iteration_limit,
             // show_mandelbrot: true,
             // show_julia: false,
             mode: Mode::Mandelbrot,
             last_mode: Mode::Mandelbrot,
             c: [0.0, 0.0],
-        })
+            escape_radius: 2.0,
+            coloring: Coloring::Banded,
+            boundary: 0.0,
+            interior: Interior::Black,
+            interior_selected: INTERIOR_GRADIENT,
+            last_interior_selected: INTERIOR_GRADIENT,
+            interior_colors,
+            interior_stops: None,
+            custom_stops: Vec::new(),
+            trap: Trap::default(),
+            show_trap: true,
+            dual_view: false,
+            show_orbit: false,
+            hovered: None,
+            pinned: None,
+            precision: Precision::Single,
+            references: HashMap::new(),
+            perturbation_supported,
+            histogram_supported,
+            use_series: true,
+            show_bookmarks: false,
+            bookmarks: Vec::new(),
+            bookmark_name: String::new(),
+            bookmark_search: String::new(),
+            renaming: None,
+            bookmark_json: String::new(),
+            bookmark_status: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            bookmark_path: "bookmarks.json".to_string(),
+            thumbnailer: Thumbnailer::new(target_format, data_format),
+            box_zoom: false,
+            box_start: None,
+            square_plot: false,
+            show_history: false,
+            history: History::default(),
+            history_candidate: None,
+            params_text: String::new(),
+            params_status: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            params_path: "fractal.toml".to_string(),
+            #[cfg(target_arch = "wasm32")]
+            link_hash: cc.integration_info.web_info.location.hash.clone(),
+            #[cfg(target_arch = "wasm32")]
+            link_fragment: String::new(),
+            #[cfg(target_arch = "wasm32")]
+            link_time: f64::NEG_INFINITY,
+        };
+        if let Some(storage) = cc.storage {
+            if let Some(session) = Session::load(storage) {
+                app.restore(session);
+            }
+            app.add_bookmarks(BookmarkCollection::load(storage).bookmarks);
+        }
+        // A shared link wins over the saved session.
+        #[cfg(target_arch = "wasm32")]
+        if !app.link_hash.is_empty() {
+            match Link::from_fragment(&app.link_hash) {
+                Ok(link) => app.apply_link(link),
+                Err(error) => log::warn!("ignoring the link: {error}"),
+            }
+        }
+        Some(app)
     }
-}
 
-fn mandelbrot_vertices() -> Vec<Vertex> {
-    let mut vertices = Vec::with_capacity(6);
-    vertices.push(Vertex {
-        position: [-2.0, -1.25],
-    });
-    vertices.push(Vertex {
-        position: [0.5, -1.25],
-    });
-    vertices.push(Vertex {
-        position: [0.5, 1.25],
-    });
-    vertices.push(Vertex {
-        position: [-2.0, -1.25],
-    });
-    vertices.push(Vertex {
-        position: [0.5, 1.25],
-    });
-    vertices.push(Vertex {
-        position: [-2.0, 1.25],
-    });
-    vertices
+    /// The state saved on exit.
+    fn session(&self) -> Session {
+        Session {
+            version: SESSION_VERSION,
+            mode: self.mode,
+            views: self.views.clone(),
+            c: self.c,
+            max_iterations: self.max_iterations,
+            escape_radius: self.escape_radius,
+            palette: self.palette_settings(),
+            coloring: self.coloring,
+            boundary: self.boundary,
+            interior: self.interior,
+            interior_gradient: self.gradient_map[&self.interior_selected].1.to_string(),
+            trap: self.trap,
+            show_trap: self.show_trap,
+            dual_view: self.dual_view,
+            show_orbit: self.show_orbit,
+            show_cpu: self.show_cpu,
+            show_gpu: self.show_gpu,
+            use_series: self.use_series,
+            square_plot: self.square_plot,
+        }
+    }
+
+    /// Restores a saved state, keeping the current gradients if it names
+    /// unknown ones and the current coloring if it is not supported.
+    fn restore(&mut self, session: Session) {
+        self.mode = session.mode;
+        self.views.extend(session.views);
+        self.c = session.c;
+        self.max_iterations = session.max_iterations.clamp(1, self.iteration_limit);
+        self.escape_radius = session.escape_radius;
+        self.apply_palette(&session.palette);
+        if session.coloring != Coloring::Histogram || self.histogram_supported {
+            self.coloring = session.coloring;
+        }
+        self.boundary = session.boundary;
+        self.interior = session.interior;
+        if let Some(key) = self.preset_key(&session.interior_gradient) {
+            self.interior_selected = key;
+        }
+        self.trap = session.trap;
+        self.show_trap = session.show_trap;
+        self.dual_view = session.dual_view;
+        self.show_orbit = session.show_orbit;
+        self.show_cpu = session.show_cpu;
+        self.show_gpu = session.show_gpu;
+        self.use_series = session.use_series;
+        self.square_plot = session.square_plot;
+    }
+
+    fn palette_settings(&self) -> PaletteSettings {
+        PaletteSettings {
+            gradient: match self.selected {
+                CUSTOM_GRADIENT => GradientSpec::Stops(self.custom_stops.clone()),
+                key => GradientSpec::Preset(self.gradient_map[&key].1.to_string()),
+            },
+            len: self.palette_len,
+            offset: self.palette.offset,
+            density: self.palette.density,
+            reverse: self.palette.reverse,
+            filter: self.palette.filter,
+        }
+    }
+
+    /// Switches to the palette of `settings`, keeping the current gradient
+    /// if it is invalid.
+    fn apply_palette(&mut self, settings: &PaletteSettings) {
+        if let Some(key) = self.gradient_key(&settings.gradient) {
+            self.selected = key;
+        }
+        self.palette_len = settings.len.clamp(2, MAX_PALETTE_LEN);
+        self.palette.offset = settings.offset;
+        self.palette.density = settings.density;
+        self.palette.reverse = settings.reverse;
+        self.palette.filter = settings.filter;
+        self.update_palette();
+        self.last_selected = self.selected;
+    }
+
+    /// The palette of `settings`, or the current one if its gradient is
+    /// invalid.
+    fn palette_of(&self, settings: &PaletteSettings) -> Palette {
+        let settings = PaletteSettings {
+            len: settings.len.clamp(2, MAX_PALETTE_LEN),
+            ..settings.clone()
+        };
+        settings.palette().unwrap_or_else(|_| self.palette.clone())
+    }
+
+    /// The key of `gradient` in `gradient_map`. Custom stops replace the
+    /// ones under `CUSTOM_GRADIENT`.
+    fn gradient_key(&mut self, gradient: &GradientSpec) -> Option<usize> {
+        match gradient {
+            GradientSpec::Preset(name) => self.preset_key(name),
+            GradientSpec::Stops(stops) => {
+                let custom = gradient.gradient().ok()?;
+                self.gradient_map
+                    .insert(CUSTOM_GRADIENT, (custom, "custom"));
+                self.custom_stops = stops.clone();
+                Some(CUSTOM_GRADIENT)
+            }
+        }
+    }
+
+    /// The key of the preset gradient called `name` in `gradient_map`.
+    fn preset_key(&self, name: &str) -> Option<usize> {
+        KEYS.into_iter()
+            .find(|key| self.gradient_map[key].1 == name)
+    }
+
+    /// The parameter file of the main plot, at the size of the plot.
+    fn params(&self) -> Params {
+        Params {
+            version: PARAMS_VERSION,
+            mode: self.mode,
+            size: self.dimensions,
+            c: self.c,
+            max_iterations: self.max_iterations,
+            escape_radius: self.escape_radius,
+            view: self.views[&self.mode].clone(),
+            palette: self.palette_settings(),
+            coloring: ColoringSettings {
+                mode: self.coloring,
+                boundary: self.boundary,
+                interior: self.interior,
+                interior_gradient: match &self.interior_stops {
+                    Some(stops) => GradientSpec::Stops(stops.clone()),
+                    None => GradientSpec::Preset(
+                        self.gradient_map[&self.interior_selected].1.to_string(),
+                    ),
+                },
+                trap: self.trap,
+            },
+        }
+    }
+
+    /// Shows the image of a parameter file in the main plot. Its size is up
+    /// to the window.
+    fn apply_params(&mut self, params: Params) {
+        self.mode = params.mode;
+        self.views.insert(params.mode, params.view);
+        self.c = params.c;
+        self.max_iterations = params.max_iterations.clamp(1, self.iteration_limit);
+        self.escape_radius = params.escape_radius;
+        self.apply_palette(&params.palette);
+        let coloring = params.coloring;
+        if coloring.mode != Coloring::Histogram || self.histogram_supported {
+            self.coloring = coloring.mode;
+        }
+        self.boundary = coloring.boundary;
+        self.interior = coloring.interior;
+        match &coloring.interior_gradient {
+            GradientSpec::Preset(name) => {
+                if let Some(key) = self.preset_key(name) {
+                    self.interior_selected = key;
+                    self.interior_stops = None;
+                }
+            }
+            // Kept until another preset is picked.
+            spec @ GradientSpec::Stops(stops) => {
+                if let Ok(gradient) = spec.gradient() {
+                    self.interior_colors = palette_colors(&*gradient, INTERIOR_LEN);
+                    self.interior_stops = Some(stops.clone());
+                }
+            }
+        }
+        self.trap = coloring.trap;
+    }
+
+    /// The buttons copying the parameter file of the main plot to a text box
+    /// and the clipboard and loading it back, and, where there are files, a
+    /// path and the buttons saving and loading it there.
+    fn params_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .button("copy")
+                .on_hover_text("Copy the parameters as TOML")
+                .clicked()
+            {
+                self.params_text = self.params().to_text(ParamsFormat::Toml);
+                ui.ctx().copy_text(self.params_text.clone());
+                self.params_status = "copied to the clipboard".to_string();
+            }
+            if ui
+                .button("apply")
+                .on_hover_text("Show the parameters of the TOML below")
+                .clicked()
+            {
+                self.params_status = match Params::from_text(&self.params_text, ParamsFormat::Toml)
+                {
+                    Ok(params) => {
+                        self.apply_params(params);
+                        "applied".to_string()
+                    }
+                    Err(error) => error,
+                };
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.params_path).desired_width(160.0));
+            let path = std::path::Path::new(&self.params_path);
+            if ui
+                .button("save")
+                .on_hover_text("Save as TOML or JSON, after the extension")
+                .clicked()
+            {
+                self.params_status = match self.params().write(path) {
+                    Ok(()) => format!("saved {}", path.display()),
+                    Err(error) => error,
+                };
+            }
+            if ui.button("load").clicked() {
+                self.params_status = match Params::read(path) {
+                    Ok(params) => {
+                        let status = format!("loaded {}", path.display());
+                        self.apply_params(params);
+                        status
+                    }
+                    Err(error) => error,
+                };
+            }
+        });
+        ui.label(&self.params_status);
+        egui::ScrollArea::vertical()
+            .id_salt("params_text")
+            .max_height(150.0)
+            .show(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.params_text)
+                        .code_editor()
+                        .desired_width(f32::INFINITY),
+                );
+            });
+    }
 }
 
-fn julia_vertices() -> Vec<Vertex> {
-    let mut vertices = Vec::with_capacity(6);
-    vertices.push(Vertex {
-        position: [-2.0, -2.0],
-    });
-    vertices.push(Vertex {
-        position: [2.0, -2.0],
-    });
-    vertices.push(Vertex {
-        position: [2.0, 2.0],
-    });
-    vertices.push(Vertex {
-        position: [-2.0, -2.0],
-    });
-    vertices.push(Vertex {
-        position: [2.0, 2.0],
-    });
-    vertices.push(Vertex {
-        position: [-2.0, 2.0],
-    });
-    vertices
+impl MyApp {
+    fn update_palette(&mut self) {
+        let preset: &(Box<dyn Gradient>, &'static str) =
+            self.gradient_map.get(&self.selected).unwrap();
+        self.palette.colors = palette_colors(&*preset.0, self.palette_len);
+    }
+
+    /// Shows the last finished CPU image as a second layer over the GPU one.
+    fn show_cpu_image(&mut self, ui: &mut egui_plot::PlotUi, name: &str) {
+        if !self.show_cpu {
+            return;
+        }
+        let view = &self.views[&self.mode];
+        let coloring = CpuColoring {
+            coloring: self.coloring,
+            palette: self.palette.clone(),
+            boundary: self.boundary,
+            interior: self.interior,
+            interior_colors: self.interior_colors.clone(),
+        };
+        if let Some((texture, rendered)) = self.cpu.image(ui.ctx(), &coloring) {
+            let (center, height) = view.locate(rendered);
+            let [width, _] = texture.size_vec2().into();
+            let aspect = width / texture.size_vec2().y;
+            ui.image(
+                PlotImage::new(
+                    name,
+                    texture.id(),
+                    PlotPoint::from(center),
+                    [height as f32 * aspect, height as f32],
+                )
+                .name(name),
+            );
+        }
+    }
+
+    /// The reference orbit for the view and where the view sits relative to
+    /// it, or `None` while it is being computed.
+    fn perturbation_frame(
+        &mut self,
+        ctx: &Context,
+        mode: Mode,
+        view: &View,
+        dimensions: [u32; 2],
+    ) -> Option<PerturbationFrame> {
+        let pixel = view.pixel_size(dimensions);
+        let key = ReferenceKey {
+            mode,
+            point: view.center.clone(),
+            c: self.c,
+            max_iterations: self.max_iterations,
+            bits: view.bits(dimensions),
+        };
+        let references = self.references.entry(mode).or_default();
+        let reference = references.get(ctx, key, pixel, dimensions)?;
+        Some(PerturbationFrame::new(
+            reference,
+            view,
+            dimensions,
+            self.use_series,
+        ))
+    }
+
+    /// Text fields for the center of the view and a drag value for its zoom.
+    fn view_controls(&mut self, ui: &mut egui::Ui) {
+        let view = self.views.get_mut(&self.mode).unwrap();
+        let bits = view.bits(self.dimensions);
+        for (i, label) in ["Re", "Im"].into_iter().enumerate() {
+            ui.label(label);
+            let text = &mut self.center_text[i];
+            let response = ui.add(egui::TextEdit::singleline(text).desired_width(200.0));
+            if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                if let Some(v) = parse_real(text, bits) {
+                    view.center[i] = v;
+                }
+            }
+            if !response.has_focus() {
+                *text = format_real(&view.center[i], bits);
+            }
+        }
+        ui.label("zoom");
+        ui.add(
+            egui::DragValue::new(&mut view.zoom)
+                .speed(0.05)
+                .range(MIN_ZOOM..=MAX_ZOOM),
+        );
+    }
+
+    fn request_cpu_image(&mut self, ctx: &Context, rect: Rect) {
+        if !self.show_cpu {
+            return;
+        }
+        self.cpu.request(
+            ctx,
+            CpuParams {
+                mode: self.mode,
+                view: self.views[&self.mode].clone(),
+                size: [rect.width() as usize, rect.height() as usize],
+                max_iterations: self.max_iterations,
+                c: self.c.map(f64::from),
+                escape_radius: f64::from(self.escape_radius),
+                trap: self.trap,
+            },
+        );
+    }
+
+    /// How the interior is colored, and from which gradient.
+    fn interior_controls(&mut self, ui: &mut egui::Ui) {
+        ui.label("interior");
+        for interior in Interior::ALL {
+            ui.radio_value(&mut self.interior, interior, interior.name());
+        }
+        if self.interior == Interior::Black {
+            return;
+        }
+        egui::ComboBox::from_id_salt("interior_gradient")
+            .selected_text(self.gradient_map[&self.interior_selected].1)
+            .show_ui(ui, |ui| {
+                for key in KEYS {
+                    ui.selectable_value(
+                        &mut self.interior_selected,
+                        key,
+                        self.gradient_map[&key].1,
+                    );
+                }
+            });
+    }
+
+    /// Shape, position and size of the orbit trap.
+    fn trap_controls(&mut self, ui: &mut egui::Ui) {
+        for shape in TrapShape::ALL {
+            ui.radio_value(&mut self.trap.shape, shape, shape.name());
+        }
+        ui.label("trap Re");
+        ui.add(egui::DragValue::new(&mut self.trap.center[0]).speed(0.005));
+        ui.label("trap Im");
+        ui.add(egui::DragValue::new(&mut self.trap.center[1]).speed(0.005));
+        if self.trap.shape.uses_angle() {
+            ui.label("angle");
+            ui.drag_angle(&mut self.trap.angle);
+        }
+        if self.trap.shape.uses_radius() {
+            ui.label(match self.trap.shape {
+                TrapShape::Stalks => "width",
+                _ => "radius",
+            });
+            ui.add(
+                egui::DragValue::new(&mut self.trap.radius)
+                    .speed(0.002)
+                    .range(0.0..=MAX_TRAP_RADIUS),
+            );
+        }
+        ui.checkbox(&mut self.show_trap, "show trap");
+    }
+
+    /// Draws the orbit trap over the plot of `view`, `dimensions` pixels
+    /// large.
+    fn show_trap_outline(&self, ui: &mut egui_plot::PlotUi, view: &View, dimensions: [u32; 2]) {
+        if self.coloring != Coloring::Trap || !self.show_trap {
+            return;
+        }
+        // Long enough for the lines to cross the whole view.
+        let bounds = view.bounds(dimensions);
+        let [x, y] = self.trap.center.map(f64::from);
+        let extent =
+            (x - bounds.center().x).hypot(y - bounds.center().y) + bounds.width() + bounds.height();
+        let color = egui::Color32::WHITE;
+        for outline in self.trap.outline(extent) {
+            let points: Vec<[f64; 2]> = outline.into_iter().map(|p| view.plot_point(p)).collect();
+            if let [point] = points[..] {
+                ui.points(Points::new("trap", point).radius(4.0).color(color));
+            } else {
+                ui.line(Line::new("trap", points).color(color));
+            }
+        }
+    }
+
+    /// The orbit of the pinned point, or else of the hovered one, with the
+    /// mode of its plot.
+    fn orbit(&self) -> Option<(Mode, Vec<[f64; 2]>)> {
+        if !self.show_orbit {
+            return None;
+        }
+        let (mode, point) = self.pinned.or(self.hovered)?;
+        let len = (self.max_iterations as usize + 1).min(ORBIT_LEN);
+        let c = self.c.map(f64::from);
+        let orbit = cpu::orbit(mode, point, c, f64::from(self.escape_radius), len);
+        Some((mode, orbit))
+    }
+
+    /// Plots `|z_n|` and `arg z_n` of `orbit` against `n`.
+    fn orbit_panel(&mut self, ui: &mut egui::Ui, orbit: Option<&[[f64; 2]]>) {
+        ui.horizontal(|ui| {
+            ui.heading("orbit");
+            if self.pinned.is_some() && ui.button("unpin").clicked() {
+                self.pinned = None;
+            }
+        });
+        let Some(orbit) = orbit else {
+            ui.label("Hover a point of the plot, or right click to pin one.");
+            return;
+        };
+        if let Some((mode, point)) = self.pinned.or(self.hovered) {
+            let [re, im] = mode.formula().start(point, self.c.map(f64::from)).c;
+            ui.label(format!("c = {re:.6} {im:+.6}i"));
+        }
+        let [re, im] = orbit[0];
+        ui.label(format!("z_0 = {re:.6} {im:+.6}i"));
+        let last = orbit[orbit.len() - 1];
+        if last[0].hypot(last[1]) > f64::from(self.escape_radius) {
+            ui.label(format!("escapes at n = {}", orbit.len() - 1));
+        } else {
+            ui.label(format!("bounded for {} iterations", orbit.len() - 1));
+        }
+        let series = |f: fn([f64; 2]) -> f64| -> Vec<[f64; 2]> {
+            orbit
+                .iter()
+                .enumerate()
+                .map(|(n, &z)| [n as f64, f(z)])
+                .collect()
+        };
+        let height = (ui.available_height() / 2.0 - ui.spacing().item_spacing.y).max(50.0);
+        for (name, values) in [
+            ("|z_n|", series(|[re, im]| re.hypot(im))),
+            ("arg z_n", series(|[re, im]| im.atan2(re))),
+        ] {
+            egui_plot::Plot::new(format!("orbit {name}"))
+                .height(height)
+                .legend(Legend::default())
+                .show(ui, |ui| {
+                    ui.line(Line::new(name, values.clone()).color(ORBIT_COLOR));
+                    ui.points(Points::new(name, values).radius(2.0).color(ORBIT_COLOR));
+                });
+        }
+    }
+
+    /// Draws `orbit` over the plot of `view`.
+    fn show_orbit(ui: &mut egui_plot::PlotUi, view: &View, orbit: &[[f64; 2]]) {
+        let points: Vec<[f64; 2]> = orbit.iter().map(|&z| view.plot_point(z)).collect();
+        ui.line(Line::new("orbit", points.clone()).color(ORBIT_COLOR));
+        ui.points(Points::new("orbit", points).radius(2.5).color(ORBIT_COLOR));
+    }
+
+    /// Bookmarks the current location of the main plot.
+    fn add_bookmark(&mut self) {
+        let name = match self.bookmark_name.trim() {
+            "" => format!("{} {}", self.mode.formula().name, self.bookmarks.len() + 1),
+            name => name.to_string(),
+        };
+        self.bookmark_name.clear();
+        self.add_bookmarks(vec![Bookmark {
+            name,
+            mode: self.mode,
+            view: self.views[&self.mode].clone(),
+            c: self.c,
+            max_iterations: self.max_iterations,
+            palette: self.palette_settings(),
+        }]);
+    }
+
+    fn add_bookmarks(&mut self, bookmarks: Vec<Bookmark>) {
+        self.bookmarks
+            .extend(bookmarks.into_iter().map(|bookmark| BookmarkEntry {
+                bookmark,
+                thumbnail: None,
+            }));
+    }
+
+    fn bookmark_collection(&self) -> BookmarkCollection {
+        BookmarkCollection::new(
+            self.bookmarks
+                .iter()
+                .map(|entry| entry.bookmark.clone())
+                .collect(),
+        )
+    }
+
+    /// Adds the bookmarks of a JSON collection.
+    fn import_bookmarks(&mut self, json: &str) {
+        self.bookmark_status = match BookmarkCollection::from_json(json) {
+            Ok(collection) => {
+                let count = collection.bookmarks.len();
+                self.add_bookmarks(collection.bookmarks);
+                format!("imported {count} bookmarks")
+            }
+            Err(error) => error,
+        };
+    }
+
+    /// Goes to the location of `bookmark`.
+    fn jump_to(&mut self, bookmark: &Bookmark) {
+        self.mode = bookmark.mode;
+        self.views.insert(bookmark.mode, bookmark.view.clone());
+        self.c = bookmark.c;
+        self.max_iterations = bookmark.max_iterations.clamp(1, self.iteration_limit);
+        self.apply_palette(&bookmark.palette);
+    }
+
+    /// The location shown, as shared in the page URL.
+    #[cfg(target_arch = "wasm32")]
+    fn link(&self) -> Link {
+        Link {
+            mode: self.mode,
+            view: self.views[&self.mode].clone(),
+            c: self.c,
+            max_iterations: self.max_iterations,
+            gradient: self.palette_settings().gradient,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn apply_link(&mut self, mut link: Link) {
+        link.view.zoom = link.view.zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.mode = link.mode;
+        self.views.insert(link.mode, link.view);
+        self.c = link.c;
+        self.max_iterations = link.max_iterations.clamp(1, self.iteration_limit);
+        self.apply_palette(&PaletteSettings {
+            gradient: link.gradient,
+            ..self.palette_settings()
+        });
+    }
+
+    /// Follows the page URL when a link is pasted into it, and otherwise
+    /// keeps it up to date with the location shown, once the pointer is
+    /// released and at most every `LINK_INTERVAL`: browsers limit how often
+    /// the history can change.
+    #[cfg(target_arch = "wasm32")]
+    fn sync_link(&mut self, ctx: &Context, frame: &Frame) {
+        let hash = frame.info().web_info.location.hash.clone();
+        if hash != self.link_hash {
+            match Link::from_fragment(&hash) {
+                Ok(link) => self.apply_link(link),
+                Err(error) => log::warn!("ignoring the link: {error}"),
+            }
+            self.link_hash = hash;
+            return;
+        }
+        let fragment = self.link().to_fragment();
+        if fragment == self.link_fragment || ctx.input(|i| i.pointer.any_down()) {
+            return;
+        }
+        let time = ctx.input(|i| i.time);
+        let wait = self.link_time + LINK_INTERVAL - time;
+        if wait > 0.0 {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(wait));
+            return;
+        }
+        let url = format!("#{fragment}");
+        let replaced = web_sys::window()
+            .ok_or(wasm_bindgen::JsValue::NULL)
+            .and_then(|window| window.history())
+            .and_then(|history| {
+                history.replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&url))
+            });
+        if let Err(error) = replaced {
+            log::warn!("cannot update the link: {error:?}");
+        }
+        self.link_fragment = fragment;
+        self.link_time = time;
+    }
+
+    /// Renders the thumbnail of the first bookmark or, if they all have one,
+    /// of the first state of the history without one, a single one per frame.
+    fn update_thumbnails(&mut self, ctx: &Context, frame: &Frame) {
+        let bookmark = self
+            .bookmarks
+            .iter()
+            .position(|entry| entry.thumbnail.is_none())
+            .filter(|_| self.show_bookmarks);
+        let state = self
+            .history
+            .entries()
+            .iter()
+            .position(|entry| entry.thumbnail.is_none())
+            .filter(|_| self.show_history);
+        let scene = match (bookmark, state) {
+            (Some(index), _) => {
+                let bookmark = &self.bookmarks[index].bookmark;
+                self.thumbnail_scene(
+                    bookmark.mode,
+                    &bookmark.view,
+                    bookmark.c,
+                    bookmark.max_iterations,
+                    &bookmark.palette,
+                )
+            }
+            (None, Some(index)) => {
+                let state = &self.history.entries()[index].state;
+                self.thumbnail_scene(
+                    state.mode,
+                    &state.view,
+                    state.c,
+                    state.max_iterations,
+                    &state.palette,
+                )
+            }
+            (None, None) => return,
+        };
+        let wgpu_render_state = frame.wgpu_render_state().unwrap();
+        let device = &wgpu_render_state.device;
+        let Some(texture) = self
+            .thumbnailer
+            .render(ctx, device, &wgpu_render_state.queue, &scene)
+        else {
+            return;
+        };
+        let texture_id = wgpu_render_state.renderer.write().register_native_texture(
+            device,
+            &texture.create_view(&Default::default()),
+            wgpu::FilterMode::Linear,
+        );
+        match (bookmark, state) {
+            (Some(index), _) => self.bookmarks[index].thumbnail = Some(texture_id),
+            (None, Some(index)) => self.history.entries_mut()[index].thumbnail = Some(texture_id),
+            (None, None) => unreachable!(),
+        }
+        ctx.request_repaint();
+    }
+
+    /// The scene of a thumbnail, colored as the main plot but for the
+    /// palette.
+    fn thumbnail_scene(
+        &self,
+        mode: Mode,
+        view: &View,
+        c: [f32; 2],
+        max_iterations: u32,
+        palette: &PaletteSettings,
+    ) -> Scene {
+        Scene {
+            mode,
+            view: view.clone(),
+            c,
+            max_iterations: max_iterations.clamp(1, self.iteration_limit),
+            escape_radius: self.escape_radius,
+            coloring: self.coloring,
+            palette: self.palette_of(palette),
+            boundary: self.boundary,
+            interior: self.interior,
+            interior_colors: self.interior_colors.clone(),
+            trap: self.trap,
+        }
+    }
+
+    /// The state undo and redo go back and forth between.
+    fn history_state(&self) -> HistoryState {
+        HistoryState {
+            mode: self.mode,
+            view: self.views[&self.mode].clone(),
+            c: self.c,
+            max_iterations: self.max_iterations,
+            palette: self.palette_settings(),
+        }
+    }
+
+    fn apply_history_state(&mut self, state: &HistoryState) {
+        self.mode = state.mode;
+        self.views.insert(state.mode, state.view.clone());
+        self.c = state.c;
+        self.max_iterations = state.max_iterations;
+        self.apply_palette(&state.palette);
+        self.history_candidate = None;
+    }
+
+    /// Adds `state` to the history, freeing the thumbnails of the states
+    /// dropped.
+    fn push_history(&mut self, frame: &Frame, state: HistoryState) {
+        let dropped = self.history.push(HistoryEntry {
+            state,
+            thumbnail: None,
+        });
+        let wgpu_render_state = frame.wgpu_render_state().unwrap();
+        let mut renderer = wgpu_render_state.renderer.write();
+        for texture_id in dropped.iter().filter_map(|entry| entry.thumbnail) {
+            renderer.free_texture(&texture_id);
+        }
+        self.history_candidate = None;
+    }
+
+    /// Adds the state shown to the history once it has been left alone for
+    /// `HISTORY_DELAY` with the pointer released.
+    fn record_history(&mut self, ctx: &Context, frame: &Frame) {
+        let state = self.history_state();
+        let current = self.history.current().map(|entry| &entry.state);
+        if current.is_none() {
+            self.push_history(frame, state);
+            return;
+        }
+        if current == Some(&state) {
+            self.history_candidate = None;
+            return;
+        }
+        let time = ctx.input(|i| i.time);
+        let since = match &self.history_candidate {
+            Some((candidate, since)) if *candidate == state => *since,
+            _ => {
+                self.history_candidate = Some((state, time));
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(HISTORY_DELAY));
+                return;
+            }
+        };
+        let wait = since + HISTORY_DELAY - time;
+        if ctx.input(|i| i.pointer.any_down()) {
+            return;
+        }
+        if wait > 0.0 {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(wait));
+            return;
+        }
+        self.push_history(frame, state);
+    }
+
+    /// Goes back to the previous state, or to the current one of the history
+    /// if the state shown is not in it yet, which redo then comes back to.
+    fn undo(&mut self, frame: &Frame) {
+        let state = self.history_state();
+        if self.history.current().map(|entry| &entry.state) != Some(&state) {
+            self.push_history(frame, state);
+        }
+        if let Some(entry) = self.history.undo() {
+            let state = entry.state.clone();
+            self.apply_history_state(&state);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(entry) = self.history.redo() {
+            let state = entry.state.clone();
+            self.apply_history_state(&state);
+        }
+    }
+
+    /// The undo and redo buttons.
+    fn history_controls(&mut self, ui: &mut egui::Ui, frame: &Frame) {
+        let ctx = ui.ctx().clone();
+        let pending =
+            self.history.current().map(|entry| &entry.state) != Some(&self.history_state());
+        if ui
+            .add_enabled(
+                self.history.can_undo() || pending,
+                egui::Button::new("undo"),
+            )
+            .on_hover_text(ctx.format_shortcut(&UNDO))
+            .clicked()
+        {
+            self.undo(frame);
+        }
+        if ui
+            .add_enabled(self.history.can_redo(), egui::Button::new("redo"))
+            .on_hover_text(ctx.format_shortcut(&REDO))
+            .clicked()
+        {
+            self.redo();
+        }
+        ui.toggle_value(&mut self.show_history, "history");
+    }
+
+    /// The states of the history with their thumbnails, oldest first, the
+    /// current one highlighted.
+    fn history_panel(&mut self, ui: &mut egui::Ui) {
+        let size = Vec2::new(THUMBNAIL_SIZE[0] as f32, THUMBNAIL_SIZE[1] as f32);
+        let current = self.history.current_index();
+        let mut go_to = None;
+        egui::ScrollArea::horizontal()
+            .stick_to_right(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (i, entry) in self.history.entries().iter().enumerate() {
+                        ui.vertical(|ui| {
+                            let selected = current == Some(i);
+                            match entry.thumbnail {
+                                Some(texture_id) => {
+                                    let image = egui::Image::new((texture_id, size));
+                                    let button = egui::Button::image(image).selected(selected);
+                                    if ui.add(button).clicked() {
+                                        go_to = Some(i);
+                                    }
+                                }
+                                None => {
+                                    ui.add_sized(size, egui::Spinner::new());
+                                }
+                            }
+                            let state = &entry.state;
+                            ui.label(format!(
+                                "{}, zoom {:.1}",
+                                state.mode.formula().name,
+                                state.view.zoom
+                            ));
+                        });
+                    }
+                });
+            });
+        if let Some(i) = go_to {
+            if let Some(entry) = self.history.go_to(i) {
+                let state = entry.state.clone();
+                self.apply_history_state(&state);
+            }
+        }
+    }
+
+    /// The bookmarks matching the search, with their thumbnails, and the
+    /// controls to add, rename, delete, import and export them.
+    fn bookmark_panel(&mut self, ui: &mut egui::Ui, frame: &Frame) {
+        ui.heading("bookmarks");
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.bookmark_name)
+                    .hint_text("name")
+                    .desired_width(160.0),
+            );
+            let entered =
+                response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+            if ui.button("add").clicked() || entered {
+                self.add_bookmark();
+            }
+        });
+        ui.add(egui::TextEdit::singleline(&mut self.bookmark_search).hint_text("search"));
+        ui.separator();
+
+        let search = self.bookmark_search.to_lowercase();
+        let size = Vec2::new(THUMBNAIL_SIZE[0] as f32, THUMBNAIL_SIZE[1] as f32);
+        let mut jump = None;
+        let mut rename = None;
+        let mut delete = None;
+        egui::ScrollArea::vertical()
+            .max_height((ui.available_height() - 250.0).max(size.y))
+            .show(ui, |ui| {
+                for (i, entry) in self.bookmarks.iter().enumerate() {
+                    let bookmark = &entry.bookmark;
+                    if !bookmark.name.to_lowercase().contains(&search) {
+                        continue;
+                    }
+                    ui.horizontal(|ui| {
+                        match entry.thumbnail {
+                            Some(texture_id) => {
+                                let image = egui::Image::new((texture_id, size));
+                                if ui.add(egui::Button::image(image)).clicked() {
+                                    jump = Some(i);
+                                }
+                            }
+                            None => {
+                                ui.add_sized(size, egui::Spinner::new());
+                            }
+                        }
+                        ui.vertical(|ui| {
+                            match &mut self.renaming {
+                                Some((renamed, name)) if *renamed == i => {
+                                    let response = ui.text_edit_singleline(name);
+                                    if response.lost_focus() {
+                                        rename = Some((i, name.clone()));
+                                    }
+                                    response.request_focus();
+                                }
+                                _ => {
+                                    if ui.link(&bookmark.name).clicked() {
+                                        jump = Some(i);
+                                    }
+                                }
+                            }
+                            ui.label(format!(
+                                "{}, zoom {:.1}",
+                                bookmark.mode.formula().name,
+                                bookmark.view.zoom
+                            ));
+                            ui.horizontal(|ui| {
+                                if ui.small_button("rename").clicked() {
+                                    self.renaming = Some((i, bookmark.name.clone()));
+                                }
+                                if ui.small_button("delete").clicked() {
+                                    delete = Some(i);
+                                }
+                            });
+                        });
+                    });
+                }
+            });
+        if let Some(i) = jump {
+            let bookmark = self.bookmarks[i].bookmark.clone();
+            self.jump_to(&bookmark);
+        }
+        if let Some((i, name)) = rename {
+            self.bookmarks[i].bookmark.name = name;
+            self.renaming = None;
+        }
+        if let Some(i) = delete {
+            let entry = self.bookmarks.remove(i);
+            if let Some(texture_id) = entry.thumbnail {
+                let wgpu_render_state = frame.wgpu_render_state().unwrap();
+                wgpu_render_state.renderer.write().free_texture(&texture_id);
+            }
+            self.renaming = None;
+        }
+
+        ui.separator();
+        ui.collapsing("import / export", |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button("export")
+                    .on_hover_text("Copy all bookmarks as JSON")
+                    .clicked()
+                {
+                    self.bookmark_json = self.bookmark_collection().to_json();
+                    ui.ctx().copy_text(self.bookmark_json.clone());
+                    self.bookmark_status = "copied to the clipboard".to_string();
+                }
+                if ui
+                    .button("import")
+                    .on_hover_text("Add the bookmarks of the JSON below")
+                    .clicked()
+                {
+                    let json = self.bookmark_json.clone();
+                    self.import_bookmarks(&json);
+                }
+            });
+            #[cfg(not(target_arch = "wasm32"))]
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.bookmark_path).desired_width(160.0));
+                if ui.button("save").clicked() {
+                    let json = self.bookmark_collection().to_json();
+                    self.bookmark_status = match std::fs::write(&self.bookmark_path, json) {
+                        Ok(()) => format!("saved {}", self.bookmark_path),
+                        Err(error) => format!("cannot write {}: {error}", self.bookmark_path),
+                    };
+                }
+                if ui.button("load").clicked() {
+                    match std::fs::read_to_string(&self.bookmark_path) {
+                        Ok(json) => self.import_bookmarks(&json),
+                        Err(error) => {
+                            self.bookmark_status =
+                                format!("cannot read {}: {error}", self.bookmark_path)
+                        }
+                    }
+                }
+            });
+            ui.label(&self.bookmark_status);
+            egui::ScrollArea::vertical()
+                .id_salt("bookmark_json")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.bookmark_json)
+                            .code_editor()
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+        });
+    }
+
+    /// Goes to the home view of `mode`, for a plot of `dimensions`.
+    fn reset_view(&mut self, mode: Mode, dimensions: [u32; 2]) {
+        let aspect = f64::from(dimensions[0].max(1)) / f64::from(dimensions[1].max(1));
+        self.views.insert(mode, View::home(mode.formula(), aspect));
+    }
+
+    /// Zooms into the rectangle dragged on the plot of `mode` in box zoom,
+    /// or out of the one dragged with the secondary button, drawing it
+    /// meanwhile.
+    fn drag_box(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        transform: &egui_plot::PlotTransform,
+        mode: Mode,
+        dimensions: [u32; 2],
+    ) {
+        use egui::PointerButton;
+        let buttons = [
+            (PointerButton::Primary, false),
+            (PointerButton::Secondary, true),
+        ];
+        let Some((button, out)) = buttons.into_iter().find(|&(button, _)| {
+            (self.box_zoom || button == PointerButton::Secondary)
+                && (response.dragged_by(button) || response.drag_stopped_by(button))
+        }) else {
+            return;
+        };
+        if response.drag_started_by(button) {
+            self.box_start = ui
+                .input(|input| input.pointer.press_origin())
+                .map(|start| (mode, start));
+        }
+        let end = ui.input(|input| input.pointer.latest_pos());
+        let Some(((_, start), end)) = self.box_start.filter(|(other, _)| *other == mode).zip(end)
+        else {
+            return;
+        };
+        let rect = Rect::from_two_pos(start, end);
+        if response.drag_stopped_by(button) {
+            self.box_start = None;
+            if rect.width().max(rect.height()) < MIN_BOX {
+                return;
+            }
+            let [min, max] = [rect.left_bottom(), rect.right_top()].map(|pos| {
+                let point = transform.value_from_position(pos);
+                [point.x, point.y]
+            });
+            let view = self.views[&mode].zoom_box(min, max, dimensions, out);
+            self.views.insert(mode, view);
+            return;
+        }
+        ui.ctx().set_cursor_icon(match out {
+            false => egui::CursorIcon::ZoomIn,
+            true => egui::CursorIcon::ZoomOut,
+        });
+        // The outline of egui_plot's own box zoom.
+        let painter = ui.painter().with_clip_rect(response.rect);
+        for (width, color) in [(4.0, egui::Color32::DARK_BLUE), (2.0, egui::Color32::WHITE)] {
+            painter.rect_stroke(
+                rect,
+                0.0,
+                egui::Stroke::new(width, color),
+                egui::StrokeKind::Middle,
+            );
+        }
+    }
+
+    /// Pans the view of the mode shown with the arrow keys and zooms it with
+    /// + and -, and goes to its home view with Home.
+    fn keyboard_navigation(&mut self, ctx: &Context) {
+        use egui::Key;
+        let (pan, zoom, home) = ctx.input(|input| {
+            if input.modifiers.command || input.modifiers.alt {
+                return ([0.0; 2], 0.0, false);
+            }
+            let pressed = |key| f64::from(u8::from(input.key_pressed(key)));
+            let pan = [
+                pressed(Key::ArrowRight) - pressed(Key::ArrowLeft),
+                pressed(Key::ArrowUp) - pressed(Key::ArrowDown),
+            ];
+            let zoom = pressed(Key::Plus).max(pressed(Key::Equals)) - pressed(Key::Minus);
+            (pan, zoom, input.key_pressed(Key::Home))
+        });
+        if home {
+            self.reset_view(self.mode, self.dimensions);
+        }
+        let view = self.views.get_mut(&self.mode).unwrap();
+        if pan != [0.0; 2] {
+            *view = view.pan(pan.map(|v| v * KEY_PAN), self.dimensions);
+        }
+        view.set_zoom(view.zoom + zoom * KEY_ZOOM);
+    }
+
+    /// The buttons and toggles of the navigation.
+    fn navigation_controls(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button("home")
+            .on_hover_text("Go to the home view (Home, or double click)")
+            .clicked()
+        {
+            self.reset_view(self.mode, self.dimensions);
+        }
+        ui.toggle_value(&mut self.box_zoom, "box zoom")
+            .on_hover_text("Drag a rectangle to zoom into it; right drag zooms out in any case");
+        ui.toggle_value(&mut self.square_plot, "square")
+            .on_hover_text("Size the plots as squares; their axes always have the same scale");
+    }
+
+    /// Plots `mode` with `orbit` over it, and queues the rendering of its
+    /// texture. In the dual view, clicking or dragging on the parameter plane
+    /// picks `c`.
+    fn show_plot(
+        &mut self,
+        ui: &mut egui::Ui,
+        frame: &Frame,
+        mode: Mode,
+        orbit: Option<&[[f64; 2]]>,
+    ) {
+        let ctx = ui.ctx().clone();
+        let formula = mode.formula();
+        let picks_c = self.dual_view && formula.plane == Plane::Parameter;
+        let plot_id = ui.make_persistent_id(format!("{}_plot", formula.name));
+        // Apply the navigation of the last frame before anything reads the
+        // view.
+        let mut plot_dimensions = self.dimensions;
+        if let Some(memory) = PlotMemory::load(&ctx, plot_id) {
+            plot_dimensions = dimensions(*memory.transform().frame());
+            let view = self.views.get_mut(&mode).unwrap();
+            view.follow_plot(memory.bounds(), plot_dimensions);
+        }
+        let view = self.views[&mode].clone();
+        let [x_labels, y_labels, hover_labels] = [(); 3].map(|_| view.clone());
+        let resolution = 1.0 / f64::from(plot_dimensions[1].max(1));
+        let wgpu_render_state = frame.wgpu_render_state().unwrap();
+        let (texel, probing) = {
+            let mut renderer = wgpu_render_state.renderer.write();
+            let renderers: &mut FractalRenderers = renderer.callback_resources.get_mut().unwrap();
+            renderers
+                .get_mut(mode)
+                .probed_texel(&wgpu_render_state.device)
+        };
+        let mut plot = egui_plot::Plot::new(format!("{}_plot", formula.name))
+            .id(plot_id)
+            .legend(Legend::default())
+            // Must set margins to zero or the image and plot bounds will
+            // constantly fight, expanding the plot to infinity.
+            .set_margin_fraction(Vec2::new(0.0, 0.0))
+            .data_aspect(1.0)
+            // Double clicks go to the home view of the formula instead.
+            .allow_double_click_reset(false)
+            // Dragging picks `c` or draws a box zoom instead.
+            .allow_drag(!picks_c && !self.box_zoom)
+            .allow_boxed_zoom(false)
+            .x_axis_formatter(move |mark, _| x_labels.label(0, mark.value, mark.step_size))
+            .y_axis_formatter(move |mark, _| y_labels.label(1, mark.value, mark.step_size))
+            .label_formatter(move |_, point| {
+                // The readout lags the pointer by a frame or two.
+                let pixel = plot_pixel([point.x, point.y], plot_dimensions);
+                let readout = match texel.filter(|texel| Some(texel.pixel) == pixel) {
+                    Some(texel) => texel_label(texel.data),
+                    None => "…".to_string(),
+                };
+                format!(
+                    "{}\n{}\n{}",
+                    hover_labels.label(0, point.x, resolution),
+                    hover_labels.label(1, point.y, resolution),
+                    readout
+                )
+            });
+        if self.square_plot {
+            let side = ui.available_size_before_wrap().min_elem();
+            plot = plot.width(side).height(side);
+        }
+        let resp = plot.show(ui, |ui| {
+            let plot_dimensions = dimensions(*ui.transform().frame());
+            let bounds = view.plot_bounds(plot_dimensions);
+            ui.set_plot_bounds(bounds);
+
+            if self.show_gpu {
+                // Render the plot texture filling the viewport.
+                ui.image(
+                    PlotImage::new(
+                        formula.name,
+                        self.texture_ids[&mode],
+                        bounds.center(),
+                        [bounds.width() as f32, bounds.height() as f32],
+                    )
+                    .name(format!("{} set (GPU)", formula.name)),
+                );
+            }
+            if mode == self.mode {
+                self.show_cpu_image(ui, &format!("{} set (CPU)", formula.name));
+            }
+            self.show_trap_outline(ui, &view, plot_dimensions);
+            if let Some(orbit) = orbit {
+                Self::show_orbit(ui, &view, orbit);
+            }
+            if picks_c {
+                let marker = view.plot_point(self.c.map(f64::from));
+                ui.points(
+                    Points::new("c", marker)
+                        .radius(5.0)
+                        .color(egui::Color32::WHITE),
+                );
+            }
+            ui.pointer_coordinate()
+        });
+        let rect = resp.response.rect;
+        let dimensions = dimensions(rect);
+        if mode == self.mode {
+            self.dimensions = dimensions;
+        }
+        if resp.response.double_clicked() {
+            self.reset_view(mode, dimensions);
+        }
+        self.drag_box(ui, &resp.response, &resp.transform, mode, dimensions);
+        let pointer = resp.inner.map(|point| {
+            view.point([point.x, point.y], view.bits(dimensions))
+                .map(|v| v.to_f64().value())
+        });
+        let response = &resp.response;
+        if let Some(point) = pointer.filter(|_| picks_c) {
+            let dragged = response.dragged_by(egui::PointerButton::Primary) && !self.box_zoom;
+            if response.clicked() || dragged {
+                self.c = point.map(|v| v as f32);
+            }
+        }
+        let hovered = pointer
+            .filter(|_| response.hovered())
+            .map(|point| (mode, point));
+        if hovered.is_some() || self.hovered.is_some_and(|(other, _)| other == mode) {
+            if self.show_orbit && hovered != self.hovered {
+                // Draw the orbit of where the pointer stopped.
+                ctx.request_repaint();
+            }
+            self.hovered = hovered;
+        }
+        if response.secondary_clicked() {
+            self.pinned = pointer.map(|point| (mode, point));
+        }
+        let probe = resp
+            .inner
+            .filter(|_| response.hovered())
+            .and_then(|point| plot_pixel([point.x, point.y], dimensions));
+        if probe.is_some() && (probing || texel.map(|texel| texel.pixel) != probe) {
+            // Show the readout once the pixel under the pointer is back.
+            ctx.request_repaint();
+        }
+
+        // Update the texture handle in egui from the previously
+        // rendered texture (from the last frame).
+        let mut renderer = wgpu_render_state.renderer.write();
+
+        let renderers: &mut FractalRenderers = renderer.callback_resources.get_mut().unwrap();
+        let util = renderers.get_mut(mode);
+
+        if self.max_iterations != util.max_iterations() {
+            util.set_max_iterations(self.max_iterations);
+        }
+        util.set_palette(&self.palette);
+        util.set_interior(self.interior, &self.interior_colors);
+        if self.c != util.c() {
+            util.set_c(self.c);
+        }
+        util.set_escape_radius(self.escape_radius);
+        util.set_coloring(self.coloring);
+        util.set_boundary(self.boundary);
+        util.set_trap(self.trap);
+
+        drop(renderer);
+
+        let precision = Precision::for_view(
+            &view,
+            dimensions,
+            self.perturbation_supported && formula.supports_perturbation(),
+        );
+        let perturbation = (precision == Precision::Perturbation)
+            .then(|| self.perturbation_frame(&ctx, mode, &view, dimensions))
+            .flatten();
+
+        // Add a callback to egui to render the plot contents to
+        // texture.
+        ui.painter()
+            .add(eframe::egui_wgpu::Callback::new_paint_callback(
+                rect,
+                FractalCallback {
+                    mode,
+                    view,
+                    rect,
+                    precision,
+                    perturbation,
+                    probe,
+                },
+            ));
+
+        let mut renderer = wgpu_render_state.renderer.write();
+        let renderers: &mut FractalRenderers = renderer.callback_resources.get_mut().unwrap();
+        let util = renderers.get_mut(mode);
+        if mode == self.mode {
+            self.precision = util.precision();
+        }
+        let texture_view = util.create_view();
+
+        renderer.update_egui_texture_from_wgpu_texture(
+            &wgpu_render_state.device,
+            &texture_view,
+            wgpu::FilterMode::Linear,
+            self.texture_ids[&mode],
+        );
+        drop(renderer);
+
+        if mode == self.mode {
+            self.request_cpu_image(&ctx, rect);
+        }
+    }
 }
 
 impl App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.session().save(storage);
+        self.bookmark_collection().save(storage);
+    }
+
     fn update(&mut self, ctx: &Context, frame: &mut Frame) {
         // static mut JULIA_PAINTED: bool = false;
-        egui::CentralPanel::default().show(ctx, |ui| unsafe {
+        #[cfg(target_arch = "wasm32")]
+        self.sync_link(ctx, frame);
+        // Text fields have undo of their own.
+        if !ctx.wants_keyboard_input() {
+            // Before `UNDO`, which it would match too.
+            if ctx.input_mut(|i| i.consume_shortcut(&REDO) || i.consume_shortcut(&REDO_Y)) {
+                self.redo();
+            } else if ctx.input_mut(|i| i.consume_shortcut(&UNDO)) {
+                self.undo(frame);
+            }
+            self.keyboard_navigation(ctx);
+        }
+        self.record_history(ctx, frame);
+        let orbit = self.orbit();
+        if self.show_bookmarks || self.show_history {
+            self.update_thumbnails(ctx, frame);
+        }
+        if self.show_history {
+            egui::TopBottomPanel::bottom("history_panel").show(ctx, |ui| {
+                ui.style_mut().visuals = eframe::egui::Visuals::dark();
+                self.history_panel(ui)
+            });
+        }
+        if self.show_bookmarks {
+            egui::SidePanel::left("bookmark_panel")
+                .default_width(300.0)
+                .show(ctx, |ui| {
+                    ui.style_mut().visuals = eframe::egui::Visuals::dark();
+                    self.bookmark_panel(ui, frame)
+                });
+        }
+        if self.show_orbit {
+            egui::SidePanel::right("orbit_panel")
+                .default_width(300.0)
+                .show(ctx, |ui| {
+                    ui.style_mut().visuals = eframe::egui::Visuals::dark();
+                    self.orbit_panel(ui, orbit.as_ref().map(|(_, orbit)| orbit.as_slice()))
+                });
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
             ui.style_mut().visuals = eframe::egui::Visuals::dark();
-            ui.horizontal(|ui| {
+            ui.horizontal_wrapped(|ui| {
                 // ui.toggle_value(&mut self.show_mandelbrot, "Mandelbrot");
                 // ui.toggle_value(&mut self.show_julia, "Julia");
-                ui.radio_value(&mut self.mode, Mode::Mandelbrot, "Mandelbrot");
-                ui.radio_value(&mut self.mode, Mode::Julia, "Julia");
+                egui::ComboBox::from_id_salt("mode")
+                    .selected_text(self.mode.formula().name)
+                    .show_ui(ui, |ui| {
+                        for mode in Mode::ALL {
+                            ui.selectable_value(&mut self.mode, mode, mode.formula().name);
+                        }
+                    });
+                let dual = self.mode.dual();
+                ui.toggle_value(
+                    &mut self.dual_view,
+                    format!("{} + Julia", dual[0].formula().name),
+                );
+                ui.toggle_value(&mut self.show_orbit, "orbit");
+                ui.toggle_value(&mut self.show_bookmarks, "bookmarks");
+                self.history_controls(ui, frame);
+                self.navigation_controls(ui);
                 ui.label("max_iterations");
                 ui.add(
-                    egui::Slider::new(&mut self.max_iterations, 128..=MAX_ITERATIONS)
+                    egui::Slider::new(&mut self.max_iterations, 128..=self.iteration_limit)
                         .step_by(128.0),
                 );
-                // ui.toggle_value(&mut self.show_cpu, "CPU");
-                // ui.toggle_value(&mut self.show_gpu, "GPU");
+                ui.toggle_value(&mut self.show_cpu, "CPU");
+                ui.toggle_value(&mut self.show_gpu, "GPU");
+                ui.label(format!("precision: {}", self.precision.name()));
+                if self.perturbation_supported {
+                    ui.checkbox(&mut self.use_series, "series approximation");
+                }
                 ui.label("color gradient");
                 egui::ComboBox::from_label("")
                     .selected_text(self.gradient_map.get(&self.selected).unwrap().1)
@@ -377,8 +1843,42 @@ impl App for MyApp {
                                 self.gradient_map.get(&key).unwrap().1,
                             );
                         }
+                        if self.gradient_map.contains_key(&CUSTOM_GRADIENT) {
+                            ui.selectable_value(&mut self.selected, CUSTOM_GRADIENT, "custom");
+                        }
                     });
-                if self.mode == Mode::Julia {
+                ui.label("stops");
+                ui.add(
+                    egui::Slider::new(&mut self.palette_len, 2..=MAX_PALETTE_LEN).logarithmic(true),
+                );
+                ui.label("offset");
+                ui.add(egui::Slider::new(&mut self.palette.offset, 0.0..=1.0));
+                ui.label("density");
+                ui.add(
+                    egui::Slider::new(&mut self.palette.density, 0.01..=100.0).logarithmic(true),
+                );
+                ui.checkbox(&mut self.palette.reverse, "reverse");
+                for filter in Filter::ALL {
+                    ui.radio_value(&mut self.palette.filter, filter, filter.name());
+                }
+                for coloring in Coloring::ALL {
+                    if coloring == Coloring::Histogram && !self.histogram_supported {
+                        continue;
+                    }
+                    ui.radio_value(&mut self.coloring, coloring, coloring.name());
+                }
+                ui.label("boundary");
+                ui.add(egui::Slider::new(&mut self.boundary, 0.0..=MAX_BOUNDARY).suffix(" px"));
+                if self.coloring == Coloring::Trap {
+                    self.trap_controls(ui);
+                }
+                self.interior_controls(ui);
+                ui.label("escape radius");
+                ui.add(
+                    egui::Slider::new(&mut self.escape_radius, 2.0..=MAX_ESCAPE_RADIUS)
+                        .logarithmic(true),
+                );
+                if self.mode.formula().uses_c() || self.dual_view {
                     ui.label("Re(c)");
                     ui.add(egui::Slider::new(&mut self.c[0], -2.0..=2.0).step_by(0.001));
                     ui.label("Im(c)");
@@ -386,201 +1886,66 @@ impl App for MyApp {
                 }
             });
 
-            if self.mode == Mode::Mandelbrot {
-                let mut bounds = PlotBounds::NOTHING;
-                let resp = egui_plot::Plot::new("Mandelbrot_plot")
-                    .legend(Legend::default())
-                    // Must set margins to zero or the image and plot bounds will
-                    // constantly fight, expanding the plot to infinity.
-                    .set_margin_fraction(Vec2::new(0.0, 0.0))
-                    .include_x(-2.0)
-                    .include_x(0.5)
-                    .include_y(-1.25)
-                    .include_y(1.25)
-                    .show(ui, |ui| {
-                        bounds = ui.plot_bounds();
-
-                        if self.show_gpu {
-                            // Render the plot texture filling the viewport.
-                            ui.image(
-                                PlotImage::new(
-                                    "Mandelbrot",
-                                    self.mandelbrot_texture_id,
-                                    bounds.center(),
-                                    [bounds.width() as f32, bounds.height() as f32],
-                                )
-                                .name("Mandelbrot set (GPU)"),
-                            );
-                        }
-                    });
-                // Update the texture handle in egui from the previously
-                // rendered texture (from the last frame).
-                let wgpu_render_state = frame.wgpu_render_state().unwrap();
-                let mut renderer = wgpu_render_state.renderer.write();
-
-                let util: &mut MandelbrotRenderUtils =
-                    renderer.callback_resources.get_mut().unwrap();
-
-                if self.max_iterations != util.max_iterations() {
-                    self.dirty = true;
-                    util.set_max_iterations(self.max_iterations);
-                }
-
-                if self.selected != self.last_selected || self.mode != self.last_mode {
-                    self.dirty = true;
-                    let preset: &(Box<dyn Gradient>, &'static str) =
-                        self.gradient_map.get(&self.selected).unwrap();
-                    let grad = preset.0.sharp(COLOR_NUM as u16, 0.);
-                    let rgba_array: [[f32; 4]; COLOR_NUM] = grad
-                        .colors(COLOR_NUM)
-                        .iter()
-                        .map(|c| [c.r as f32, c.g as f32, c.b as f32, c.a as f32])
-                        .collect::<Vec<[f32; 4]>>()
-                        .try_into()
-                        .unwrap();
-                    util.set_palette(rgba_array);
-                }
+            ui.horizontal(|ui| {
+                self.view_controls(ui);
+            });
+            ui.collapsing("parameters", |ui| self.params_controls(ui));
 
-                // Add a callback to egui to render the plot contents to
-                // texture.
-                // ui.painter().add(mandelbrot::egui_wgpu_callback(
-                //     bounds,
-                //     Arc::clone(&self.mandelbrot_points),
-                //     resp.response.rect,
-                //     self.dirty,
-                // ));
-                ui.painter()
-                    .add(eframe::egui_wgpu::Callback::new_paint_callback(
-                        Rect {
-                            min: resp.response.rect.min,
-                            max: resp.response.rect.max,
-                        },
-                        mandelbrot::MandelbrotCallback {
-                            bounds,
-                            points: Arc::clone(&self.mandelbrot_points),
-                            rect: resp.response.rect,
-                            dirty: self.dirty,
-                        },
-                    ));
-
-                let texture_view = util.create_view();
-
-                renderer.update_egui_texture_from_wgpu_texture(
-                    &wgpu_render_state.device,
-                    &texture_view,
-                    wgpu::FilterMode::Linear,
-                    self.mandelbrot_texture_id,
-                );
+            if self.selected != self.last_selected || self.palette_len != self.palette.colors.len()
+            {
+                self.update_palette();
+            }
+            if self.interior_selected != self.last_interior_selected {
+                let gradient = &*self.gradient_map[&self.interior_selected].0;
+                self.interior_colors = palette_colors(gradient, INTERIOR_LEN);
+                self.interior_stops = None;
+                self.last_interior_selected = self.interior_selected;
             }
 
-            if self.mode == Mode::Julia {
-                // if !JULIA_PAINTED {
-                //     self.dirty = true;
-                //     JULIA_PAINTED = true;
-                // }
-                let mut bounds = PlotBounds::NOTHING;
-                let resp = egui_plot::Plot::new("Julia_plot")
-                    .legend(Legend::default())
-                    // Must set margins to zero or the image and plot bounds will
-                    // constantly fight, expanding the plot to infinity.
-                    .set_margin_fraction(Vec2::new(0.0, 0.0))
-                    .include_x(-2.0)
-                    .include_x(2.0)
-                    .include_y(-2.0)
-                    .include_y(2.0)
-                    .show(ui, |ui| {
-                        bounds = ui.plot_bounds();
-
-                        if self.show_gpu {
-                            // Render the plot texture filling the viewport.
-                            ui.image(
-                                PlotImage::new(
-                                    "Julia",
-                                    self.julia_texture_id,
-                                    bounds.center(),
-                                    [bounds.width() as f32, bounds.height() as f32],
-                                )
-                                .name("Julia set (GPU)"),
-                            );
-                        }
-                    });
-                // Update the texture handle in egui from the previously
-                // rendered texture (from the last frame).
-                let wgpu_render_state = frame.wgpu_render_state().unwrap();
-                let mut renderer = wgpu_render_state.renderer.write();
-
-                let util: &mut JuliaRenderUtils = renderer.callback_resources.get_mut().unwrap();
-
-                if self.max_iterations != util.max_iterations() {
-                    self.dirty = true;
-                    util.set_max_iterations(self.max_iterations);
-                }
-
-                if self.selected != self.last_selected || self.mode != self.last_mode {
-                    self.dirty = true;
-                    let preset: &(Box<dyn Gradient>, &'static str) =
-                        self.gradient_map.get(&self.selected).unwrap();
-                    let grad = preset.0.sharp(COLOR_NUM as u16, 0.);
-                    let rgba_array: [[f32; 4]; COLOR_NUM] = grad
-                        .colors(COLOR_NUM)
-                        .iter()
-                        .map(|c| [c.r as f32, c.g as f32, c.b as f32, c.a as f32])
-                        .collect::<Vec<[f32; 4]>>()
-                        .try_into()
-                        .unwrap();
-                    util.set_palette(rgba_array);
-                }
-
-                if self.c != util.c() {
-                    self.dirty = true;
-                    util.set_c(self.c);
-                }
-
-                // Add a callback to egui to render the plot contents to
-                // texture.
-                // ui.painter().add(julia::egui_wgpu_callback(
-                //     bounds,
-                //     Arc::clone(&self.julia_points),
-                //     resp.response.rect,
-                //     self.dirty,
-                // ));
-                ui.painter()
-                    .add(eframe::egui_wgpu::Callback::new_paint_callback(
-                        resp.response.rect,
-                        julia::JuliaCallback {
-                            bounds,
-                            points: Arc::clone(&self.julia_points),
-                            rect: resp.response.rect,
-                            dirty: self.dirty,
-                        },
-                    ));
-
-                let texture_view = util.create_view();
-
-                renderer.update_egui_texture_from_wgpu_texture(
-                    &wgpu_render_state.device,
-                    &texture_view,
-                    wgpu::FilterMode::Linear,
-                    self.julia_texture_id,
-                );
+            let orbit_of = |mode| {
+                orbit
+                    .as_ref()
+                    .filter(|(orbit_mode, _)| *orbit_mode == mode)
+                    .map(|(_, orbit)| orbit.as_slice())
+            };
+            if self.dual_view {
+                ui.columns(2, |columns| {
+                    for (ui, mode) in columns.iter_mut().zip(self.mode.dual()) {
+                        self.show_plot(ui, frame, mode, orbit_of(mode));
+                    }
+                });
+            } else {
+                self.show_plot(ui, frame, self.mode, orbit_of(self.mode));
             }
 
-            self.dirty = false;
             self.last_selected = self.selected;
-            self.last_mode = self.mode.clone();
+            self.last_mode = self.mode;
         });
     }
 }
 
+/// The hover readout of the data of a pixel.
+fn texel_label(data: PixelData) -> String {
+    match data {
+        PixelData::Outside => "outside the domain".to_string(),
+        PixelData::Interior { period: 0, .. } => "interior".to_string(),
+        PixelData::Interior { period, .. } => format!("interior, period {period}"),
+        PixelData::Escaped {
+            iterations, smooth, ..
+        } => format!("escaped after {iterations} iterations\nsmooth {smooth:.3}"),
+    }
+}
+
+fn dimensions(rect: Rect) -> [u32; 2] {
+    [rect.width() as u32, rect.height() as u32]
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
-    let grad = colorgrad::preset::cubehelix_default().sharp(COLOR_NUM as u16, 0.);
-    // let colors=grad.take(1000).collect::<Vec<_>>();
-    let colors = grad.colors(COLOR_NUM);
-    let mut rgba_array = [[0.0; 4]; COLOR_NUM];
-    for (i, c) in colors.iter().enumerate() {
-        rgba_array[i] = [c.r as f32, c.g as f32, c.b as f32, c.a as f32];
-    }
+    let palette = Palette::new(palette_colors(
+        &colorgrad::preset::cubehelix_default(),
+        PALETTE_LEN,
+    ));
 
 
 
@@ -645,9 +2010,9 @@ fn main() {
         ..Default::default()
     };
     // let app_creator: Box<dyn FnOnce(&CreationContext<'_>) -> Box<dyn App>> =
-    //     Box::new(move |cc| Box::new(MyApp::new(cc, rgba_array).unwrap()));
+    //     Box::new(move |cc| Box::new(MyApp::new(cc, palette).unwrap()));
     let app_creator: AppCreator<'_> = Box::new(move |cc| {
-        let app = MyApp::new(cc, rgba_array).unwrap();
+        let app = MyApp::new(cc, palette).unwrap();
         Ok(Box::new(app) as Box<dyn App>)
     });
     eframe::run_native("Fractal Plotter", native_options, app_creator)
@@ -656,15 +2021,10 @@ fn main() {
 
 #[cfg(target_arch = "wasm32")]
 fn main() {
-    let grad = colorgrad::preset::cubehelix_default().sharp(COLOR_NUM as u16, 0.);
-    // let colors=grad.take(1000).collect::<Vec<_>>();
-    let colors = grad.colors(COLOR_NUM);
-    static mut rgba_array: [[f32; 4]; 128] = [[0.0; 4]; COLOR_NUM];
-    for (i, c) in colors.iter().enumerate() {
-        unsafe {
-            rgba_array[i] = [c.r as f32, c.g as f32, c.b as f32, c.a as f32];
-        }
-    }
+    let palette = Palette::new(palette_colors(
+        &colorgrad::preset::cubehelix_default(),
+        PALETTE_LEN,
+    ));
 
     use eframe::wasm_bindgen::JsCast as _;
 
@@ -724,7 +2084,7 @@ fn main() {
                 .expect("the_canvas_id was not a HtmlCanvasElement");
 
             let app_creator: AppCreator<'static> = Box::new(move |cc| {
-                let app = MyApp::new(cc, rgba_array).unwrap();
+                let app = MyApp::new(cc, palette).unwrap();
                 Ok(Box::new(app) as Box<dyn App>)
             });
 
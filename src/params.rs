@@ -0,0 +1,371 @@
+use crate::coloring::{Coloring, Interior, INTERIOR_LEN, MAX_BOUNDARY};
+use crate::fractal::{Mode, Scene, MAX_ESCAPE_RADIUS, MAX_ITERATIONS};
+use crate::gradient::{palette_colors, GradientSpec};
+use crate::session::PaletteSettings;
+use crate::trap::Trap;
+use crate::view::View;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Version of `Params` written by this build, see `SESSION_VERSION`.
+pub const PARAMS_VERSION: u32 = 1;
+
+/// Largest width and height of an image.
+pub const MAX_SIZE: u32 = 65536;
+
+/// A fractal parameter file: everything an image depends on, so that it can
+/// be rendered again exactly, by the GUI or by `fractal-render --params`.
+///
+/// It is written in TOML or JSON, after the extension of the file. Only
+/// `version`, `mode` and `view` are required, and values out of the ranges
+/// offered by the GUI are rejected, see `Params::validate()`:
+///
+/// ```toml
+/// version = 1
+/// mode = "Mandelbrot"             # or "Julia", "BurningShip", "BurningShipJulia", …
+/// size = [1920, 1080]             # of the image, in pixels
+/// c = [-0.8, 0.156]               # the constant of Julia
+/// max_iterations = 1024
+/// escape_radius = 2.0
+///
+/// [view]
+/// center = ["-0.75", "0"]         # in decimal, with as many digits as needed
+/// zoom = -1.3                     # the view is 2^-zoom tall
+///
+/// [palette]
+/// gradient = "cubehelix_default"  # a preset, or stops: ["#000000", "#ff8800", "#ffffff"]
+/// len = 128                       # entries sampled from the gradient
+/// offset = 0.0
+/// density = 1.0
+/// reverse = false
+/// filter = "Nearest"              # or "Linear"
+///
+/// [coloring]
+/// mode = "Smooth"                 # "Banded", "Smooth", "Histogram", "Distance" or "Trap"
+/// boundary = 0.0
+/// interior = "Black"              # "Black", "Period", "Modulus" or "Multiplier"
+/// interior_gradient = "viridis"
+///
+/// [coloring.trap]
+/// shape = "Point"                 # "Point", "Line", "Cross", "Circle" or "Stalks"
+/// center = [0.0, 0.0]
+/// angle = 0.0
+/// radius = 0.1
+/// ```
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Params {
+    pub version: u32,
+    pub mode: Mode,
+    #[serde(default = "default_size")]
+    pub size: [u32; 2],
+    #[serde(default)]
+    pub c: [f32; 2],
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: u32,
+    #[serde(default = "default_escape_radius")]
+    pub escape_radius: f32,
+    pub view: View,
+    #[serde(default)]
+    pub palette: PaletteSettings,
+    #[serde(default)]
+    pub coloring: ColoringSettings,
+}
+
+/// How the data of the pixels is colored, besides the palette.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColoringSettings {
+    pub mode: Coloring,
+    /// Width of the darkened exterior along the boundary, in pixels.
+    pub boundary: f32,
+    pub interior: Interior,
+    pub interior_gradient: GradientSpec,
+    pub trap: Trap,
+}
+
+impl ColoringSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        in_range("boundary", self.boundary, 0.0..=MAX_BOUNDARY)?;
+        self.interior_gradient.gradient()?;
+        self.trap.validate()
+    }
+}
+
+impl Default for ColoringSettings {
+    fn default() -> Self {
+        ColoringSettings {
+            mode: Coloring::Banded,
+            boundary: 0.0,
+            interior: Interior::Black,
+            interior_gradient: GradientSpec::Preset("viridis".to_owned()),
+            trap: Trap::default(),
+        }
+    }
+}
+
+fn default_size() -> [u32; 2] {
+    [1024, 1024]
+}
+
+fn default_max_iterations() -> u32 {
+    1024
+}
+
+fn default_escape_radius() -> f32 {
+    2.0
+}
+
+/// The syntax of a parameter file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParamsFormat {
+    Toml,
+    Json,
+}
+
+impl ParamsFormat {
+    /// The format of `path`, after its extension.
+    pub fn from_path(path: &Path) -> Option<ParamsFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "toml" => Some(ParamsFormat::Toml),
+            "json" => Some(ParamsFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Just the version of a parameter file, to pick how to read the rest.
+#[derive(Deserialize)]
+struct ParamsVersion {
+    version: u32,
+}
+
+impl Params {
+    /// Checks that the values are within the ranges offered by the GUI, as
+    /// parameter files come from anywhere and out of range values can stall
+    /// the renderer, like deep zooms whose centers take too many bits.
+    pub fn validate(&self) -> Result<(), String> {
+        for size in self.size {
+            in_range("size", size, 1..=MAX_SIZE)?;
+        }
+        self.c.into_iter().try_for_each(|v| finite("c", v))?;
+        in_range("max_iterations", self.max_iterations, 1..=MAX_ITERATIONS)?;
+        in_range("escape_radius", self.escape_radius, 2.0..=MAX_ESCAPE_RADIUS)?;
+        self.view.validate()?;
+        self.palette.validate()?;
+        self.coloring.validate()
+    }
+
+    /// The scene of the image.
+    pub fn scene(&self) -> Result<Scene, String> {
+        let interior_gradient = self.coloring.interior_gradient.gradient()?;
+        Ok(Scene {
+            mode: self.mode,
+            view: self.view.clone(),
+            c: self.c,
+            max_iterations: self.max_iterations,
+            escape_radius: self.escape_radius,
+            coloring: self.coloring.mode,
+            palette: self.palette.palette()?,
+            boundary: self.coloring.boundary,
+            interior: self.coloring.interior,
+            interior_colors: palette_colors(&*interior_gradient, INTERIOR_LEN),
+            trap: self.coloring.trap,
+        })
+    }
+
+    pub fn to_text(&self, format: ParamsFormat) -> String {
+        match format {
+            ParamsFormat::Toml => toml::to_string(self).expect("parameters serialize"),
+            ParamsFormat::Json => serde_json::to_string_pretty(self).expect("parameters serialize"),
+        }
+    }
+
+    pub fn from_text(text: &str, format: ParamsFormat) -> Result<Params, String> {
+        fn parse<T: for<'de> Deserialize<'de>>(
+            text: &str,
+            format: ParamsFormat,
+        ) -> Result<T, String> {
+            match format {
+                ParamsFormat::Toml => toml::from_str(text).map_err(|error| error.to_string()),
+                ParamsFormat::Json => serde_json::from_str(text).map_err(|error| error.to_string()),
+            }
+        }
+        let version = parse::<ParamsVersion>(text, format)
+            .map_err(|error| format!("not a parameter file: {error}"))?
+            .version;
+        let params: Params = match version {
+            PARAMS_VERSION => {
+                parse(text, format).map_err(|error| format!("invalid parameter file: {error}"))
+            }
+            _ => Err(format!("unknown parameter file version {version}")),
+        }?;
+        params
+            .validate()
+            .map_err(|error| format!("invalid parameter file: {error}"))?;
+        Ok(params)
+    }
+
+    /// Reads the parameter file at `path`, in the format of its extension.
+    pub fn read(path: &Path) -> Result<Params, String> {
+        let format = format_of(path)?;
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("cannot read {}: {error}", path.display()))?;
+        Params::from_text(&text, format).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    /// Writes the parameter file at `path`, in the format of its extension.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = self.to_text(format_of(path)?);
+        std::fs::write(path, text)
+            .map_err(|error| format!("cannot write {}: {error}", path.display()))
+    }
+}
+
+/// Checks that `value` of the field `name` is within `range`, which NaN never
+/// is.
+pub fn in_range<T: PartialOrd + Display>(
+    name: &str,
+    value: T,
+    range: RangeInclusive<T>,
+) -> Result<(), String> {
+    match range.contains(&value) {
+        true => Ok(()),
+        false => Err(format!(
+            "{name} is {value}, out of {}..={}",
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+/// Checks that `value` of the field `name` is a finite number.
+pub fn finite(name: &str, value: f32) -> Result<(), String> {
+    match value.is_finite() {
+        true => Ok(()),
+        false => Err(format!("{name} is {value}, not a finite number")),
+    }
+}
+
+fn format_of(path: &Path) -> Result<ParamsFormat, String> {
+    ParamsFormat::from_path(path).ok_or(format!(
+        "unknown parameter file format of {}, expected .toml or .json",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coloring::Filter;
+    use crate::trap::TrapShape;
+    use crate::view::parse_real;
+
+    const MINIMAL: &str = r#"
+        version = 1
+        mode = "Julia"
+
+        [view]
+        center = ["-0.75", "0.1"]
+        zoom = -1.5
+    "#;
+
+    fn params() -> Params {
+        Params {
+            version: PARAMS_VERSION,
+            mode: Mode::BurningShip,
+            size: [640, 480],
+            c: [-0.8, 0.156],
+            max_iterations: 5000,
+            escape_radius: 100.0,
+            view: View {
+                center: ["-1.75", "-0.0625"].map(|v| parse_real(v, 0).unwrap()),
+                zoom: 12.5,
+            },
+            palette: PaletteSettings {
+                gradient: GradientSpec::Stops(vec!["#000000".to_owned(), "#ff8800".to_owned()]),
+                len: 64,
+                offset: 0.25,
+                density: 3.0,
+                reverse: true,
+                filter: Filter::Linear,
+            },
+            coloring: ColoringSettings {
+                mode: Coloring::Trap,
+                boundary: 1.5,
+                interior: Interior::Period,
+                interior_gradient: GradientSpec::Preset("magma".to_owned()),
+                trap: Trap {
+                    shape: TrapShape::Cross,
+                    center: [0.5, -0.25],
+                    angle: 0.75,
+                    radius: 0.2,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut custom = params();
+        custom.coloring.interior_gradient =
+            GradientSpec::Stops(vec!["#102030".to_owned(), "#ffffff".to_owned()]);
+        for params in [params(), custom] {
+            for format in [ParamsFormat::Toml, ParamsFormat::Json] {
+                let text = params.to_text(format);
+                assert_eq!(
+                    Params::from_text(&text, format),
+                    Ok(params.clone()),
+                    "{text}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let params = Params::from_text(MINIMAL, ParamsFormat::Toml).unwrap();
+        assert_eq!(params.mode, Mode::Julia);
+        assert_eq!(params.size, default_size());
+        assert_eq!(params.max_iterations, default_max_iterations());
+        assert_eq!(params.escape_radius, default_escape_radius());
+        assert_eq!(params.palette, PaletteSettings::default());
+        assert_eq!(params.coloring, ColoringSettings::default());
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        let invalid = [
+            "size = [0, 480]",
+            "c = [nan, 0.0]",
+            "max_iterations = 0",
+            "max_iterations = 1000000",
+            "escape_radius = 1.0",
+            "escape_radius = inf",
+            "palette = { len = 0 }",
+            "palette = { density = nan }",
+            r#"palette = { gradient = "no such preset" }"#,
+            "coloring = { boundary = -1.0 }",
+            "coloring = { trap = { radius = 100.0 } }",
+            "coloring = { trap = { angle = inf } }",
+        ];
+        for line in invalid {
+            let text = MINIMAL.replace("mode", &format!("{line}\nmode"));
+            assert!(
+                Params::from_text(&text, ParamsFormat::Toml).is_err(),
+                "{line}"
+            );
+        }
+        for zoom in ["1e9", "-1e9", "nan"] {
+            let text = MINIMAL.replace("-1.5", zoom);
+            assert!(
+                Params::from_text(&text, ParamsFormat::Toml).is_err(),
+                "{zoom}"
+            );
+        }
+        let text = MINIMAL.replace("version = 1", "version = 2");
+        assert!(Params::from_text(&text, ParamsFormat::Toml).is_err());
+    }
+}
@@ -0,0 +1,853 @@
+use crate::fractal::{df64, Mode, Plane, MAX_ESCAPE_RADIUS};
+use crate::wgsl_struct::PerturbationParams;
+use dashu_float::round::mode::HalfEven;
+use dashu_float::FBig;
+use eframe::egui;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// Arbitrary-precision binary float used for reference orbits.
+pub type Real = FBig<HalfEven>;
+
+/// Squared radius beyond which reference orbits stop, so that they outlast
+/// any escape radius picked for the pixels.
+const BAILOUT: f64 = MAX_ESCAPE_RADIUS as f64 * MAX_ESCAPE_RADIUS as f64;
+
+/// Series approximation stops once its truncation error reaches this
+/// fraction of the first-order term, about one f32 ulp.
+const SERIES_TOLERANCE_LOG2: f64 = -24.0;
+
+/// ... and while deltas stay this small, no pixel can escape or glitch
+/// before the skipped iterations are over.
+const SERIES_MAX_DELTA_LOG2: f64 = -8.0;
+
+/// Bits kept below the pixel size when computing a reference, and extra bits
+/// requested so that zooming in does not immediately need a new reference.
+const GUARD_BITS: usize = 32;
+const HEADROOM_BITS: usize = 64;
+
+/// Iterations of a reference orbit between checks for cancellation.
+const CANCEL_INTERVAL: usize = 1024;
+
+/// Iterations of a reference orbit run per frame at most on the web, where it
+/// is computed on the UI thread a slice at a time, times its bits of
+/// precision: iterations get slower with more bits.
+#[cfg(target_arch = "wasm32")]
+const WEB_REFERENCE_BITS_PER_FRAME: usize = 1 << 20;
+
+/// Decomposes `v` into a mantissa in `[0.5, 1)` and an exponent.
+fn frexp(v: f64) -> (f64, i64) {
+    if v == 0.0 || !v.is_finite() {
+        return (v, 0);
+    }
+    let biased = ((v.to_bits() >> 52) & 0x7ff) as i64;
+    if biased == 0 {
+        // Subnormal: scale into the normal range first.
+        let (mantissa, exponent) = frexp(v * 2f64.powi(64));
+        return (mantissa, exponent - 64);
+    }
+    let exponent = biased - 1022;
+    (v * 2f64.powi(-exponent as i32), exponent)
+}
+
+/// A real number `mantissa · 2^exponent` with an exponent range wide enough
+/// for pixel sizes far below `f64::MIN_POSITIVE`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RealExp {
+    pub mantissa: f64,
+    pub exponent: i64,
+}
+
+// Plain methods rather than the operator traits, like the WGSL counterparts.
+#[allow(clippy::should_implement_trait)]
+impl RealExp {
+    pub fn new(v: f64) -> RealExp {
+        let (mantissa, exponent) = frexp(v);
+        RealExp { mantissa, exponent }
+    }
+
+    pub fn from_real(v: &Real) -> RealExp {
+        let repr = v.repr();
+        // Keep the 64 leading bits of the significand, the rest is below f64
+        // precision anyway.
+        let shift = repr.digits().saturating_sub(64);
+        let mantissa = (repr.significand().clone() >> shift).to_f64().value();
+        let (mantissa, exponent) = frexp(mantissa);
+        RealExp {
+            mantissa,
+            exponent: exponent + repr.exponent() as i64 + shift as i64,
+        }
+    }
+
+    /// `2^v`, for any `v` that fits an i64 once truncated.
+    pub fn exp2(v: f64) -> RealExp {
+        let exponent = v.floor();
+        RealExp::new((v - exponent).exp2()).scale(exponent as i64)
+    }
+
+    /// `self · 2^exponent`.
+    pub fn scale(self, exponent: i64) -> RealExp {
+        RealExp {
+            exponent: self.exponent + exponent,
+            ..self
+        }
+    }
+
+    pub fn mul(self, other: RealExp) -> RealExp {
+        let (mantissa, exponent) = frexp(self.mantissa * other.mantissa);
+        RealExp {
+            mantissa,
+            exponent: exponent + self.exponent + other.exponent,
+        }
+    }
+
+    pub fn div(self, other: RealExp) -> RealExp {
+        let (mantissa, exponent) = frexp(self.mantissa / other.mantissa);
+        RealExp {
+            mantissa,
+            exponent: exponent + self.exponent - other.exponent,
+        }
+    }
+
+    pub fn log2(self) -> f64 {
+        self.mantissa.abs().log2() + self.exponent as f64
+    }
+
+    /// Saturates to 0 or infinity outside of the f64 range.
+    pub fn to_f64(self) -> f64 {
+        // In two steps, `2^exponent` alone may not be an f64 when the result
+        // is, for subnormals.
+        let exponent = self.exponent.clamp(-2000, 2000) as i32;
+        self.mantissa * 2f64.powi(exponent / 2) * 2f64.powi(exponent - exponent / 2)
+    }
+}
+
+/// A complex number whose parts share one extended exponent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ComplexExp {
+    pub mantissa: [f64; 2],
+    pub exponent: i64,
+}
+
+#[allow(clippy::should_implement_trait)]
+impl ComplexExp {
+    pub const ZERO: ComplexExp = ComplexExp {
+        mantissa: [0.0, 0.0],
+        exponent: 0,
+    };
+    pub const ONE: ComplexExp = ComplexExp {
+        mantissa: [0.5, 0.0],
+        exponent: 1,
+    };
+
+    fn normalized(mantissa: [f64; 2], exponent: i64) -> ComplexExp {
+        let (_, shift) = frexp(mantissa[0].abs().max(mantissa[1].abs()));
+        let scale = 2f64.powi(-shift as i32);
+        ComplexExp {
+            mantissa: mantissa.map(|m| m * scale),
+            exponent: exponent + shift,
+        }
+    }
+
+    pub fn new(v: [f64; 2]) -> ComplexExp {
+        ComplexExp::normalized(v, 0)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.mantissa == [0.0, 0.0]
+    }
+
+    pub fn add(self, other: ComplexExp) -> ComplexExp {
+        if self.is_zero() {
+            return other;
+        }
+        if other.is_zero() {
+            return self;
+        }
+        let (hi, lo) = if self.exponent >= other.exponent {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let shift = lo.exponent - hi.exponent;
+        if shift < -64 {
+            return hi;
+        }
+        let scale = 2f64.powi(shift as i32);
+        ComplexExp::normalized(
+            [
+                hi.mantissa[0] + lo.mantissa[0] * scale,
+                hi.mantissa[1] + lo.mantissa[1] * scale,
+            ],
+            hi.exponent,
+        )
+    }
+
+    pub fn mul(self, other: ComplexExp) -> ComplexExp {
+        let [a, b] = self.mantissa;
+        let [c, d] = other.mantissa;
+        ComplexExp::normalized(
+            [a * c - b * d, a * d + b * c],
+            self.exponent + other.exponent,
+        )
+    }
+
+    pub fn log2_abs(self) -> f64 {
+        if self.is_zero() {
+            return f64::NEG_INFINITY;
+        }
+        self.mantissa[0].hypot(self.mantissa[1]).log2() + self.exponent as f64
+    }
+
+    /// `(re, im, exponent, 0)`, as read by `perturbation.wgsl`.
+    pub fn to_gpu(self) -> [f32; 4] {
+        if self.is_zero() {
+            return [0.0; 4];
+        }
+        [
+            self.mantissa[0] as f32,
+            self.mantissa[1] as f32,
+            self.exponent as f32,
+            0.0,
+        ]
+    }
+}
+
+/// Everything a reference orbit depends on.
+#[derive(Clone, PartialEq)]
+pub struct ReferenceKey {
+    pub mode: Mode,
+    /// The point of the plane the orbit starts from.
+    pub point: [Real; 2],
+    pub c: [f32; 2],
+    pub max_iterations: u32,
+    /// Bits of precision of the computation.
+    pub bits: usize,
+}
+
+/// A high-precision orbit computed on the CPU, rounded to df64 pairs for the
+/// GPU, along with the coefficients of its series approximation.
+pub struct ReferenceOrbit {
+    pub key: ReferenceKey,
+    /// `(re hi, im hi, re lo, im lo)` of `Z_0, Z_1, …` up to and including
+    /// the first escaped point, followed for dynamical-plane formulas by the
+    /// orbit of the critical point 0, which glitched pixels are rebased onto.
+    /// In the parameter plane the reference orbit is itself critical.
+    pub orbits: Vec<[f32; 4]>,
+    pub primary_len: usize,
+    /// `[A_n, B_n, C_n]` with `δ_n ≈ A_n d + B_n d² + C_n d³`, `d` being the
+    /// offset of a pixel from the reference point.
+    series: Vec<[ComplexExp; 3]>,
+}
+
+/// How the current view sits relative to a reference.
+pub struct PerturbationView {
+    /// Position of the reference point in pixels from the top left corner.
+    pub reference_pixel: [f64; 2],
+    /// Width and height of one pixel.
+    pub pixel_size: [RealExp; 2],
+    pub dimensions: [u32; 2],
+}
+
+impl ReferenceOrbit {
+    /// Computes the orbit of `key` on the current thread.
+    pub fn new(key: ReferenceKey) -> ReferenceOrbit {
+        Self::compute(key, || false).expect("never cancelled")
+    }
+
+    /// Computes the orbit of `key`, giving up with `None` once `cancelled`
+    /// returns true.
+    fn compute(key: ReferenceKey, cancelled: impl Fn() -> bool) -> Option<ReferenceOrbit> {
+        let mut job = ReferenceJob::new(key);
+        while !job.advance(CANCEL_INTERVAL) {
+            if cancelled() {
+                return None;
+            }
+        }
+        Some(job.finish())
+    }
+
+    /// Number of iterations the series approximation can skip for every pixel
+    /// within `radius` of the reference point.
+    fn skip(&self, radius: RealExp) -> usize {
+        let log2_radius = radius.log2();
+        // Pixels stay close to the reference while skipping, so none escapes
+        // before the reference leaves the smallest escape radius, 2.
+        let escaped = self.orbits[..self.primary_len]
+            .iter()
+            .position(|[x, y, _, _]| x * x + y * y > 4.0)
+            .unwrap_or(self.primary_len - 1);
+        let max_skip = escaped.min(self.key.max_iterations as usize);
+        self.series[..=max_skip]
+            .iter()
+            .position(|[a, _, c]| {
+                let first_order = a.log2_abs() + log2_radius;
+                let third_order = c.log2_abs() + 3.0 * log2_radius;
+                third_order - first_order > SERIES_TOLERANCE_LOG2
+                    || first_order > SERIES_MAX_DELTA_LOG2
+            })
+            .map_or(max_skip, |invalid| invalid.saturating_sub(1))
+    }
+
+    pub fn params(&self, view: &PerturbationView, use_series: bool) -> PerturbationParams {
+        let [pixel_x, pixel_y] = view.pixel_size;
+        let exponent = pixel_x.exponent.max(pixel_y.exponent);
+        let pixel_scale = [pixel_x, pixel_y]
+            .map(|p| (p.mantissa * 2f64.powi((p.exponent - exponent) as i32)) as f32);
+
+        // The farthest a pixel of the view is from the reference point.
+        let [w, h] = view.dimensions.map(f64::from);
+        let [rx, ry] = view.reference_pixel;
+        let dx = rx.abs().max((w - rx).abs());
+        let dy = ry.abs().max((h - ry).abs());
+        let radius = RealExp::new(dx.hypot(dy)).mul(RealExp {
+            mantissa: 1.0,
+            exponent,
+        });
+
+        let skip = if use_series { self.skip(radius) } else { 0 };
+        let [a, b, c] = self.series[skip].map(ComplexExp::to_gpu);
+        let (critical_offset, critical_len) = match self.key.mode.formula().plane {
+            Plane::Parameter => (0, self.primary_len),
+            Plane::Dynamical => (self.primary_len, self.orbits.len() - self.primary_len),
+        };
+        PerturbationParams {
+            series_a: a,
+            series_b: b,
+            series_c: c,
+            reference_pixel: view.reference_pixel.map(|p| p as f32),
+            pixel_scale,
+            pixel_exponent: exponent as f32,
+            skip: skip as u32,
+            primary_len: self.primary_len as u32,
+            critical_offset: critical_offset as u32,
+            critical_len: critical_len as u32,
+            plane: plane_id(self.key.mode.formula().plane),
+            padding0: 0,
+            padding1: 0,
+        }
+    }
+}
+
+impl ReferenceKey {
+    /// Whether the orbit of this key can serve as the reference of the view
+    /// described by `view`, whose pixels are `pixel_size` wide and whose
+    /// largest side is `extent` pixels long.
+    fn fits(&self, view: &ReferenceKey, pixel_size: RealExp, extent: f64) -> bool {
+        if self.mode != view.mode
+            || self.max_iterations != view.max_iterations
+            || (view.mode.formula().uses_c() && self.c != view.c)
+            || self.bits < view.bits
+        {
+            return false;
+        }
+        // Rebasing copes with any reference, but far away ones glitch a lot.
+        (0..2).all(|i| {
+            let offset = RealExp::from_real(&(&self.point[i] - &view.point[i]));
+            offset.log2() - pixel_size.log2() < extent.log2() + 2.0
+        })
+    }
+}
+
+fn plane_id(plane: Plane) -> u32 {
+    match plane {
+        Plane::Parameter => 0,
+        Plane::Dynamical => 1,
+    }
+}
+
+/// A reference orbit being computed, a slice of iterations at a time.
+struct ReferenceJob {
+    key: ReferenceKey,
+    /// The orbit of the reference point, then for dynamical-plane formulas
+    /// the orbit of the critical point.
+    orbits: Vec<Orbit>,
+}
+
+impl ReferenceJob {
+    fn new(key: ReferenceKey) -> ReferenceJob {
+        let bits = key.bits;
+        let real = |v: f32| {
+            Real::try_from(f64::from(v))
+                .unwrap()
+                .with_precision(bits)
+                .value()
+        };
+        let c = key.c.map(real);
+        let zero = [real(0.0), real(0.0)];
+        let orbits = match key.mode.formula().plane {
+            Plane::Parameter => vec![Orbit::new(zero, key.point.clone(), key.max_iterations)],
+            Plane::Dynamical => vec![
+                Orbit::new(key.point.clone(), c.clone(), key.max_iterations),
+                Orbit::new(zero, c, key.max_iterations),
+            ],
+        };
+        ReferenceJob { key, orbits }
+    }
+
+    /// Runs at most `budget` iterations, returning whether the orbits are
+    /// complete.
+    fn advance(&mut self, mut budget: usize) -> bool {
+        self.orbits
+            .iter_mut()
+            .all(|orbit| orbit.advance(&mut budget))
+    }
+
+    /// The reference of the complete orbits.
+    fn finish(self) -> ReferenceOrbit {
+        let plane = self.key.mode.formula().plane;
+        let mut orbits = self.orbits.into_iter().map(|orbit| orbit.points);
+        let mut primary = orbits.next().unwrap();
+        let primary_len = primary.len();
+        let series = series(&primary, plane);
+        primary.extend(orbits.flatten());
+        ReferenceOrbit {
+            key: self.key,
+            orbits: primary,
+            primary_len,
+            series,
+        }
+    }
+}
+
+/// The iteration of `z -> z^2 + c` from some `z` until it escapes or
+/// `max_iterations` is reached.
+struct Orbit {
+    z: [Real; 2],
+    c: [Real; 2],
+    max_iterations: u32,
+    /// The points so far, as `(re hi, im hi, re lo, im lo)`.
+    points: Vec<[f32; 4]>,
+    done: bool,
+}
+
+impl Orbit {
+    fn new(z: [Real; 2], c: [Real; 2], max_iterations: u32) -> Orbit {
+        Orbit {
+            z,
+            c,
+            max_iterations,
+            points: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Runs at most `budget` iterations, taking them off it, and returns
+    /// whether the orbit is complete.
+    fn advance(&mut self, budget: &mut usize) -> bool {
+        let [x, y] = &mut self.z;
+        while !self.done && *budget > 0 {
+            *budget -= 1;
+            let [x_hi, x_lo] = df64(x);
+            let [y_hi, y_lo] = df64(y);
+            self.points.push([x_hi, y_hi, x_lo, y_lo]);
+            let (xf, yf) = (x.to_f64().value(), y.to_f64().value());
+            if xf * xf + yf * yf > BAILOUT || self.points.len() > self.max_iterations as usize {
+                self.done = true;
+                break;
+            }
+            let xy = &*x * &*y;
+            *x = &*x * &*x - &*y * &*y + &self.c[0];
+            *y = &xy + &xy + &self.c[1];
+        }
+        self.done
+    }
+}
+
+/// Coefficients of `δ_n ≈ A_n d + B_n d² + C_n d³` along `orbit`, from
+/// `δ_{n+1} = 2 Z_n δ_n + δ_n² (+ d in the parameter plane)`.
+fn series(orbit: &[[f32; 4]], plane: Plane) -> Vec<[ComplexExp; 3]> {
+    let (mut a, dc) = match plane {
+        Plane::Parameter => (ComplexExp::ZERO, ComplexExp::ONE),
+        Plane::Dynamical => (ComplexExp::ONE, ComplexExp::ZERO),
+    };
+    let mut b = ComplexExp::ZERO;
+    let mut c = ComplexExp::ZERO;
+    let two = ComplexExp::new([2.0, 0.0]);
+    let mut series = Vec::with_capacity(orbit.len());
+    for [x_hi, y_hi, x_lo, y_lo] in orbit {
+        series.push([a, b, c]);
+        let two_z = two.mul(ComplexExp::new([
+            f64::from(*x_hi) + f64::from(*x_lo),
+            f64::from(*y_hi) + f64::from(*y_lo),
+        ]));
+        let next_c = two_z.mul(c).add(two.mul(a).mul(b));
+        let next_b = two_z.mul(b).add(a.mul(a));
+        a = two_z.mul(a).add(dc);
+        b = next_b;
+        c = next_c;
+    }
+    series
+}
+
+/// Bits of precision needed to tell pixels of `pixel_size` apart around a
+/// point of magnitude up to 2.
+pub fn bits_for(pixel_size: RealExp) -> usize {
+    (1.0 - pixel_size.log2()).max(0.0) as usize + GUARD_BITS
+}
+
+/// Computes reference orbits in the background and hands out the last one
+/// that fits the view.
+pub struct References {
+    current: Option<Arc<ReferenceOrbit>>,
+    requested: Option<ReferenceKey>,
+    generation: Arc<AtomicU64>,
+    /// The reference being computed on the web, with its generation.
+    #[cfg(target_arch = "wasm32")]
+    job: Option<(u64, ReferenceJob)>,
+    sender: Sender<(u64, ReferenceOrbit)>,
+    receiver: Receiver<(u64, ReferenceOrbit)>,
+}
+
+impl Default for References {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        References {
+            current: None,
+            requested: None,
+            generation: Arc::new(AtomicU64::new(0)),
+            #[cfg(target_arch = "wasm32")]
+            job: None,
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl References {
+    /// Returns a reference for the view centered on `view.point`, if one
+    /// fitting it is ready, and starts computing one at that point otherwise.
+    /// `view.bits` should come from `bits_for(pixel_size)`.
+    pub fn get(
+        &mut self,
+        ctx: &egui::Context,
+        view: ReferenceKey,
+        pixel_size: RealExp,
+        dimensions: [u32; 2],
+    ) -> Option<Arc<ReferenceOrbit>> {
+        #[cfg(target_arch = "wasm32")]
+        self.advance(ctx);
+        if let Some((_, reference)) = self
+            .receiver
+            .try_iter()
+            .filter(|(generation, _)| *generation == self.generation.load(Ordering::SeqCst))
+            .last()
+        {
+            self.current = Some(Arc::new(reference));
+        }
+
+        let extent = f64::from(dimensions[0].max(dimensions[1]));
+        let fits = |key: &ReferenceKey| key.fits(&view, pixel_size, extent);
+        if let Some(current) = self.current.as_ref().filter(|current| fits(&current.key)) {
+            return Some(Arc::clone(current));
+        }
+        if !self.requested.as_ref().is_some_and(fits) {
+            let bits = view.bits + HEADROOM_BITS;
+            let key = ReferenceKey {
+                point: view.point.map(|v| v.with_precision(bits).value()),
+                bits,
+                ..view
+            };
+            self.request(ctx, key);
+        }
+        None
+    }
+
+    fn request(&mut self, ctx: &egui::Context, key: ReferenceKey) {
+        self.requested = Some(key.clone());
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let current = Arc::clone(&self.generation);
+            let cancelled = move || current.load(Ordering::Relaxed) != generation;
+            let sender = self.sender.clone();
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                if let Some(reference) = ReferenceOrbit::compute(key, cancelled) {
+                    if sender.send((generation, reference)).is_ok() {
+                        ctx.request_repaint();
+                    }
+                }
+            });
+        }
+
+        // No threads on the web: compute in place, a slice per frame.
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.job = Some((generation, ReferenceJob::new(key)));
+            ctx.request_repaint();
+        }
+    }
+
+    /// Runs the next slice of the reference being computed on the web,
+    /// dropping it once cancelled by a newer request.
+    #[cfg(target_arch = "wasm32")]
+    fn advance(&mut self, ctx: &egui::Context) {
+        let Some((generation, job)) = &mut self.job else {
+            return;
+        };
+        if *generation != self.generation.load(Ordering::SeqCst) {
+            self.job = None;
+            return;
+        }
+        let budget = WEB_REFERENCE_BITS_PER_FRAME / job.key.bits.max(1);
+        if !job.advance(budget.max(1)) {
+            ctx.request_repaint();
+        } else if let Some((generation, job)) = self.job.take() {
+            let _ = self.sender.send((generation, job.finish()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coloring::{self, Coloring, Interior, Palette};
+    use crate::cpu;
+    use crate::fractal::{FractalRenderer, PerturbationFrame, Precision, Scene};
+    use crate::readback::PixelData;
+    use crate::trap::Trap;
+    use crate::view::{parse_real, View};
+    use eframe::wgpu;
+
+    fn normalized(v: RealExp) -> bool {
+        (0.5..1.0).contains(&v.mantissa.abs())
+    }
+
+    #[test]
+    fn real_exp_normalization() {
+        for v in [1.0, -3.0, 0.75, 1e-300, f64::MIN_POSITIVE / 1024.0, 1e300] {
+            let r = RealExp::new(v);
+            assert!(normalized(r), "{v}: {r:?}");
+            assert_eq!(r.to_f64(), v);
+        }
+        assert_eq!(RealExp::new(0.0).to_f64(), 0.0);
+        let r = RealExp::exp2(-5000.25);
+        assert!(normalized(r));
+        assert!((r.log2() + 5000.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn real_exp_arithmetic() {
+        let small = RealExp::exp2(-3000.0).mul(RealExp::new(3.0));
+        let large = RealExp::exp2(2990.0);
+        assert!(normalized(small.mul(large)) && normalized(small.div(large)));
+        assert_eq!(small.mul(large).to_f64(), 3.0 / 1024.0);
+        assert!((small.div(large).log2() - (3f64.log2() - 5990.0)).abs() < 1e-12);
+        assert_eq!(RealExp::new(-6.0).div(RealExp::new(4.0)).to_f64(), -1.5);
+        assert_eq!(RealExp::new(5.0).scale(-2000).scale(2000).to_f64(), 5.0);
+        // Saturation outside of the f64 range.
+        assert_eq!(small.to_f64(), 0.0);
+        assert_eq!(large.to_f64(), f64::INFINITY);
+    }
+
+    #[test]
+    fn real_exp_from_real() {
+        let v = (Real::try_from(3.0).unwrap() >> 5000)
+            .with_precision(300)
+            .value();
+        let r = RealExp::from_real(&v);
+        assert!(normalized(r));
+        assert!((r.log2() - (3f64.log2() - 5000.0)).abs() < 1e-12);
+        assert_eq!(
+            RealExp::from_real(&Real::try_from(-0.375).unwrap()).to_f64(),
+            -0.375
+        );
+    }
+
+    #[test]
+    fn complex_exp_arithmetic() {
+        let a = ComplexExp::new([3.0, -5.0]);
+        assert!((0.5..1.0).contains(&a.mantissa[0].abs().max(a.mantissa[1].abs())));
+        assert_eq!(
+            a.mantissa.map(|m| m * 2f64.powi(a.exponent as i32)),
+            [3.0, -5.0]
+        );
+        // (3 - 5i)(1 + 2i) = 13 + i
+        let product = a.mul(ComplexExp::new([1.0, 2.0]));
+        assert_eq!(
+            product
+                .mantissa
+                .map(|m| m * 2f64.powi(product.exponent as i32)),
+            [13.0, 1.0]
+        );
+        let tiny = ComplexExp {
+            mantissa: [0.5, 0.0],
+            exponent: -2000,
+        };
+        assert_eq!(a.add(tiny), a);
+        assert_eq!(tiny.add(tiny).exponent, -1999);
+        assert_eq!(ComplexExp::ZERO.add(a), a);
+        assert!((ComplexExp::ONE.log2_abs()).abs() < 1e-15);
+        assert_eq!(ComplexExp::ZERO.to_gpu(), [0.0; 4]);
+    }
+
+    /// References computed a slice at a time, as on the web, are the same as
+    /// those computed at once.
+    #[test]
+    fn reference_in_slices() {
+        for mode in [Mode::Mandelbrot, Mode::Julia] {
+            let key = ReferenceKey {
+                mode,
+                point: ["-0.1", "0.1"].map(|v| parse_real(v, 0).unwrap()),
+                c: [-0.8, 0.156],
+                max_iterations: 3000,
+                bits: 128,
+            };
+            let whole = ReferenceOrbit::new(key.clone());
+            let mut job = ReferenceJob::new(key);
+            let mut slices = 1;
+            while !job.advance(100) {
+                slices += 1;
+            }
+            let sliced = job.finish();
+            assert!(slices > 1);
+            assert_eq!(sliced.orbits, whole.orbits);
+            assert_eq!(sliced.primary_len, whole.primary_len);
+            assert!(sliced.series == whole.series);
+        }
+        assert!(ReferenceOrbit::compute(
+            ReferenceKey {
+                mode: Mode::Mandelbrot,
+                point: ["0", "0"].map(|v| parse_real(v, 0).unwrap()),
+                c: [0.0; 2],
+                max_iterations: 5000,
+                bits: 64,
+            },
+            || true
+        )
+        .is_none());
+    }
+
+    /// The series skip gives the same deltas as iterating them.
+    #[test]
+    fn series_matches_iteration() {
+        let c = [-0.5, 0.5].map(|v| Real::try_from(v).unwrap().with_precision(128).value());
+        let zero = [0.0, 0.0].map(|v| Real::try_from(v).unwrap().with_precision(128).value());
+        let mut orbit = Orbit::new(zero, c, 40);
+        assert!(orbit.advance(&mut 100));
+        let orbit = orbit.points;
+        let series = series(&orbit, Plane::Parameter);
+        let d = [1e-7, 5e-8];
+        let mul = |a: [f64; 2], b: [f64; 2]| [a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]];
+        let mut delta = [0.0, 0.0];
+        for (n, [x_hi, y_hi, x_lo, y_lo]) in orbit.iter().enumerate().take(40) {
+            let to_f64 = |v: ComplexExp| v.mantissa.map(|m| m * 2f64.powi(v.exponent as i32));
+            let [a, b, c] = series[n].map(to_f64);
+            let d2 = mul(d, d);
+            let approximation =
+                [0, 1].map(|i| mul(a, d)[i] + mul(b, d2)[i] + mul(c, mul(d2, d))[i]);
+            let error = (approximation[0] - delta[0]).hypot(approximation[1] - delta[1]);
+            assert!(
+                error <= 1e-6 * delta[0].hypot(delta[1]),
+                "{n}: {approximation:?} {delta:?}"
+            );
+            let z = [
+                f64::from(*x_hi) + f64::from(*x_lo),
+                f64::from(*y_hi) + f64::from(*y_lo),
+            ];
+            let two_z_delta = mul([2.0 * z[0], 2.0 * z[1]], delta);
+            let delta2 = mul(delta, delta);
+            delta = [0, 1].map(|i| two_z_delta[i] + delta2[i] + d[i]);
+        }
+    }
+
+    /// The escape counts of the perturbation shader match f64 around the
+    /// period-3 minibrot with the reference off its nucleus, where pixels
+    /// near the nucleus pass much closer to 0 than the reference does: the
+    /// classic Pauldelbrot glitch.
+    #[test]
+    fn perturbation_matches_exact_counts_at_a_glitch() {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let Ok(adapter) = pollster::block_on(instance.request_adapter(&Default::default())) else {
+            eprintln!("no wgpu adapter, skipping");
+            return;
+        };
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))
+        .unwrap();
+        let size = [64, 64];
+        let max_iterations = 2000;
+        let view = View {
+            center: ["-1.7548776662466927", "0"].map(|v| parse_real(v, 0).unwrap()),
+            zoom: 4.0,
+        };
+        let scene = Scene {
+            mode: Mode::Mandelbrot,
+            view: view.clone(),
+            c: [0.0; 2],
+            max_iterations,
+            escape_radius: 2.0,
+            coloring: Coloring::Banded,
+            palette: Palette::new(vec![[0.0; 4]]),
+            boundary: 0.0,
+            interior: Interior::Black,
+            interior_colors: vec![[0.0; 4]],
+            trap: Trap::default(),
+        };
+        let mut renderer = FractalRenderer::for_scene(
+            &device,
+            wgpu::TextureFormat::Rgba8Unorm,
+            coloring::data_format(&adapter),
+            &scene,
+        );
+        if !renderer.supports_perturbation() {
+            eprintln!("no perturbation, skipping");
+            return;
+        }
+        let bits = view.bits(size) + 64;
+        let reference = Arc::new(ReferenceOrbit::new(ReferenceKey {
+            mode: Mode::Mandelbrot,
+            point: view.point([0.3, 0.2], bits),
+            c: [0.0; 2],
+            max_iterations,
+            bits,
+        }));
+        let frame = PerturbationFrame::new(reference, &view, size, false);
+        renderer.prepare(
+            &device,
+            &queue,
+            size,
+            &view,
+            Precision::Perturbation,
+            Some(&frame),
+        );
+        renderer.render(&device, &queue);
+        let readback = renderer.read_back(&device, &queue);
+
+        let mut wrong = 0;
+        let [width, height] = size.map(f64::from);
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                let offset = [
+                    (f64::from(x) + 0.5 - width / 2.0) / height,
+                    (height / 2.0 - f64::from(y) - 0.5) / height,
+                ];
+                let point = view.point(offset, bits).map(|v| v.to_f64().value());
+                let orbit = cpu::orbit(
+                    Mode::Mandelbrot,
+                    point,
+                    [0.0; 2],
+                    2.0,
+                    max_iterations as usize,
+                );
+                let [x_n, y_n] = orbit[orbit.len() - 1];
+                let exact = (x_n * x_n + y_n * y_n > 4.0).then_some(orbit.len() as u32);
+                let gpu = match readback.pixel([x, y]).unwrap().1 {
+                    PixelData::Escaped { iterations, .. } => Some(iterations),
+                    _ => None,
+                };
+                if gpu != exact {
+                    wrong += 1;
+                }
+            }
+        }
+        // Chaotic pixels near the boundary part ways with f64 after hundreds
+        // of iterations; without rebasing, about a third of them are wrong.
+        assert!(wrong < size[0] * size[1] / 100, "{wrong} pixels are wrong");
+    }
+}
@@ -0,0 +1,199 @@
+// Perturbation theory: every pixel iterates its difference δ from a reference
+// orbit Z computed in arbitrary precision on the CPU,
+//
+//     δ_{n+1} = 2 Z_n δ_n + δ_n² + δc,
+//
+// where δc is the offset of the pixel in the parameter plane and 0 in the
+// dynamical plane. The derivative of z = Z + δ with respect to the point of the
+// plane follows dz -> 2 z dz + 1 (or + 0 in the dynamical plane), like in
+// `escape()`. Deltas are far below the f32 range at deep zooms, so they
+// are kept as a mantissa with a separate exponent.
+//
+// A pixel is rebased when its orbit gets closer to 0 than its delta is,
+// |Z + δ| < |δ|, or when the reference ends: it is then re-referenced onto the
+// orbit of the critical point 0, δ := Z + δ, and the reference index restarts,
+// see Zhuoran, "Another solution to perturbation glitches" (2021).
+//
+// This detects every glitch that Pauldelbrot's criterion |Z + δ| < t |Z| does,
+// for any tolerance t < 1/2: then |δ| >= |Z| - |Z + δ| > (1/t - 1) |Z + δ|,
+// so |Z + δ| < |δ| already holds. Unlike Pauldelbrot's, it also fixes the
+// pixel instead of leaving it to a new reference, so it is the only check.
+
+struct PerturbationParams {
+    series_a: vec4<f32>,
+    series_b: vec4<f32>,
+    series_c: vec4<f32>,
+    reference_pixel: vec2<f32>,
+    pixel_scale: vec2<f32>,
+    pixel_exponent: f32,
+    skip: u32,
+    primary_len: u32,
+    critical_offset: u32,
+    critical_len: u32,
+    plane: u32,
+    padding0: u32,
+    padding1: u32,
+};
+
+const PLANE_PARAMETER: u32 = 0u;
+
+@group(0) @binding(1)
+var<uniform> perturbation: PerturbationParams;
+
+// (re hi, im hi, re lo, im lo) of the reference and critical orbits.
+@group(0) @binding(2)
+var<storage, read> reference: array<vec4<f32>>;
+
+// m · 2^e with max(|m.x|, |m.y|) in [0.5, 1), or m = 0 and e = EXP_ZERO.
+struct ComplexExp {
+    m: vec2<f32>,
+    e: i32,
+};
+
+const EXP_ZERO: i32 = -0x10000000;
+
+fn ce_zero() -> ComplexExp {
+    return ComplexExp(vec2<f32>(0.0, 0.0), EXP_ZERO);
+}
+
+fn ce_new(m: vec2<f32>, e: i32) -> ComplexExp {
+    let largest = max(abs(m.x), abs(m.y));
+    if (largest == 0.0) {
+        return ce_zero();
+    }
+    let shift = frexp(largest).exp;
+    return ComplexExp(ldexp(m, vec2<i32>(-shift)), e + shift);
+}
+
+// (re, im, exponent, 0) as written by `ComplexExp::to_gpu`.
+fn ce_unpack(packed: vec4<f32>) -> ComplexExp {
+    return ce_new(packed.xy, i32(packed.z));
+}
+
+fn ce_add(a: ComplexExp, b: ComplexExp) -> ComplexExp {
+    var hi = a;
+    var lo = b;
+    if (a.e < b.e) {
+        hi = b;
+        lo = a;
+    }
+    let shift = lo.e - hi.e;
+    if (shift < -48) {
+        return hi;
+    }
+    return ce_new(hi.m + ldexp(lo.m, vec2<i32>(shift)), hi.e);
+}
+
+fn ce_mul(a: ComplexExp, b: ComplexExp) -> ComplexExp {
+    let m = vec2<f32>(a.m.x * b.m.x - a.m.y * b.m.y, a.m.x * b.m.y + a.m.y * b.m.x);
+    return ce_new(m, a.e + b.e);
+}
+
+// |a|² as a plain f32, saturating outside of its range.
+fn ce_norm_sqr(a: ComplexExp) -> f32 {
+    return ldexp(dot(a.m, a.m), clamp(2 * a.e, -126, 127));
+}
+
+// |a| as a plain f32, saturating outside of its range.
+fn ce_abs(a: ComplexExp) -> f32 {
+    return ldexp(length(a.m), clamp(a.e, -126, 127));
+}
+
+// `a` as a plain vec2, saturating outside of the f32 range.
+fn ce_vec(a: ComplexExp) -> vec2<f32> {
+    return ldexp(a.m, vec2<i32>(clamp(a.e, -126, 127)));
+}
+
+// |a| < |b|, comparing exponents first so that nothing overflows.
+fn ce_less(a: ComplexExp, b: ComplexExp) -> bool {
+    if (a.e == EXP_ZERO) {
+        return b.e != EXP_ZERO;
+    }
+    if (b.e == EXP_ZERO) {
+        return false;
+    }
+    let shift = clamp(2 * (a.e - b.e), -126, 126);
+    return ldexp(dot(a.m, a.m), shift) < dot(b.m, b.m);
+}
+
+@fragment
+fn fs_perturbation(in: VertexOut) -> @location(0) vec4<f32> {
+    return escape_data(escape_perturbed(in.position.xy));
+}
+
+fn escape_perturbed(position: vec2<f32>) -> Escape {
+    // Fragment rows go down while the imaginary axis goes up.
+    let offset = vec2<f32>(
+        position.x - perturbation.reference_pixel.x,
+        perturbation.reference_pixel.y - position.y,
+    );
+    let d = ce_new(offset * perturbation.pixel_scale, i32(perturbation.pixel_exponent));
+
+    let one = ce_new(vec2<f32>(1.0, 0.0), 0);
+    var delta = d;
+    var dc = ce_zero();
+    // The derivatives of z and c with respect to the point of the plane.
+    var dz = one;
+    var dc_dpoint = ce_zero();
+    if (perturbation.plane == PLANE_PARAMETER) {
+        delta = ce_zero();
+        dc = d;
+        dz = ce_zero();
+        dc_dpoint = one;
+    }
+
+    var n = 0u;
+    if (perturbation.skip > 0u) {
+        let a = ce_unpack(perturbation.series_a);
+        let b = ce_unpack(perturbation.series_b);
+        let c = ce_unpack(perturbation.series_c);
+        let d2 = ce_mul(d, d);
+        delta = ce_add(ce_add(ce_mul(a, d), ce_mul(b, d2)), ce_mul(c, ce_mul(d2, d)));
+        // The derivative of the series.
+        let two_b = ComplexExp(b.m, b.e + 1);
+        let three_c = ce_new(3.0 * c.m, c.e);
+        dz = ce_add(ce_add(a, ce_mul(two_b, d)), ce_mul(three_c, d2));
+        n = perturbation.skip;
+    }
+    // The pixel height, to get distance estimates in pixels.
+    let pixel = ce_new(
+        vec2<f32>(perturbation.pixel_scale.y, 0.0),
+        i32(perturbation.pixel_exponent),
+    );
+
+    var trap = TRAP_FAR;
+    var periodicity = periodicity_new();
+    var last = vec2<f32>(0.0, 0.0);
+    var m = n;
+    var offset_index = 0u;
+    var len = perturbation.primary_len;
+    for (; n < uniforms.max_iterations; n++) {
+        let packed = reference[offset_index + m];
+        let reference_z = ce_new(packed.xy, 0);
+        // Add the low part last, it matters when Z and δ nearly cancel.
+        let z = ce_add(ce_add(reference_z, delta), ce_new(packed.zw, 0));
+        last = ce_vec(z);
+        // Iterations skipped by the series are left out of the trap.
+        if (n > 0u) {
+            trap = min(trap, trap_distance(last));
+        }
+        if (ce_norm_sqr(z) > escape_radius_sqr()) {
+            let distance = distance_estimate(last, ce_abs(ce_mul(dz, pixel)));
+            return escaped(n + 1u, last, distance, trap);
+        }
+        periodicity_step(&periodicity, n, last);
+        if (m + 1u >= len || ce_less(z, delta)) {
+            // Rebase onto the critical orbit, which starts at 0.
+            delta = z;
+            offset_index = perturbation.critical_offset;
+            len = perturbation.critical_len;
+            m = 0u;
+        }
+        dz = ce_add(ce_mul(ComplexExp(z.m, z.e + 1), dz), dc_dpoint);
+        let z_m = ce_new(reference[offset_index + m].xy, 0);
+        let two_z = ComplexExp(z_m.m, z_m.e + 1);
+        delta = ce_add(ce_add(ce_mul(two_z, delta), ce_mul(delta, delta)), dc);
+        m++;
+    }
+    return no_escape(last, trap, periodicity);
+}
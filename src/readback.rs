@@ -0,0 +1,278 @@
+use eframe::wgpu;
+use std::sync::{Arc, Mutex};
+
+/// Offset of the color texel in the buffer of `TexelProbe`, after the data
+/// texel.
+const PROBE_COLOR_OFFSET: u64 = wgpu::COPY_BUFFER_ALIGNMENT * 64;
+
+/// What the data texture holds for one pixel, see `coloring.wgsl`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelData {
+    /// Outside of the domain of the formula.
+    Outside,
+    /// The orbit did not escape. `period` is 0 unless a cycle was detected,
+    /// `modulus` is the final `|z|` and `multiplier` the modulus of the cycle
+    /// multiplier.
+    Interior {
+        period: u32,
+        modulus: f32,
+        multiplier: f32,
+    },
+    /// The orbit escaped after `iterations`, `smooth` being the continuous
+    /// count, `distance` the distance estimate in pixels and `trap` the
+    /// distance of the orbit to the trap.
+    Escaped {
+        iterations: u32,
+        smooth: f32,
+        distance: f32,
+        trap: f32,
+    },
+}
+
+impl PixelData {
+    pub fn new(texel: [f32; 4]) -> PixelData {
+        let [a, b, c, d] = texel;
+        if a < 0.0 {
+            PixelData::Outside
+        } else if a == 0.0 {
+            PixelData::Interior {
+                period: b as u32,
+                modulus: c,
+                multiplier: d,
+            }
+        } else {
+            PixelData::Escaped {
+                iterations: a as u32,
+                smooth: b,
+                distance: c,
+                trap: d,
+            }
+        }
+    }
+}
+
+/// One pixel read back by `TexelProbe`: its position from the top left,
+/// color and data.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Texel {
+    pub pixel: [u32; 2],
+    pub rgba: [u8; 4],
+    pub data: PixelData,
+}
+
+/// The colored image and the data texture of a renderer, copied back from
+/// the GPU, as rows from the top.
+pub struct Readback {
+    pub size: [u32; 2],
+    pub rgba: Vec<[u8; 4]>,
+    pub data: Vec<[f32; 4]>,
+}
+
+impl Readback {
+    /// The color and data of pixel `[x, y]`, from the top left.
+    pub fn pixel(&self, [x, y]: [u32; 2]) -> Option<([u8; 4], PixelData)> {
+        if x >= self.size[0] || y >= self.size[1] {
+            return None;
+        }
+        let i = (y * self.size[0] + x) as usize;
+        Some((self.rgba[i], PixelData::new(self.data[i])))
+    }
+}
+
+/// Copies `texture` back from the GPU, as tightly packed rows from the top.
+/// Blocks until the GPU is done, which the web does not allow.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Vec<u8> {
+    let size = texture.size();
+    let texel_size = texel_size(texture.format());
+    let row_len = size.width * texel_size;
+    // Buffer copies need aligned rows.
+    let padded_row_len = row_len.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("egui_plot_readback"),
+        size: u64::from(padded_row_len) * u64::from(size.height),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_len),
+                rows_per_image: None,
+            },
+        },
+        size,
+    );
+    queue.submit(core::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| {
+        result.expect("failed to map the readback buffer")
+    });
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .expect("failed to wait for the readback");
+    let mapped = slice.get_mapped_range();
+    mapped
+        .chunks(padded_row_len as usize)
+        .flat_map(|row| &row[..row_len as usize])
+        .copied()
+        .collect()
+}
+
+fn texel_size(format: wgpu::TextureFormat) -> u32 {
+    format
+        .block_copy_size(None)
+        .expect("a single-aspect format")
+}
+
+/// RGBA colors from the bytes of a texture of an 8-bit `format`, swapping
+/// the channels of the BGRA ones.
+pub fn rgba_texels(format: wgpu::TextureFormat, bytes: &[u8]) -> Vec<[u8; 4]> {
+    let bgra = matches!(
+        format,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    );
+    bytes
+        .chunks_exact(4)
+        .map(|texel| match bgra {
+            true => [texel[2], texel[1], texel[0], texel[3]],
+            false => [texel[0], texel[1], texel[2], texel[3]],
+        })
+        .collect()
+}
+
+/// Data texels from the bytes of a texture of the float `format` of the data
+/// texture, see `coloring::data_format()`.
+pub fn data_texels(format: wgpu::TextureFormat, bytes: &[u8]) -> Vec<[f32; 4]> {
+    match format {
+        wgpu::TextureFormat::Rgba16Float => bytes
+            .chunks_exact(8)
+            .map(|texel| {
+                [0, 1, 2, 3]
+                    .map(|i| half::f16::from_le_bytes([texel[2 * i], texel[2 * i + 1]]).to_f32())
+            })
+            .collect(),
+        _ => bytemuck::pod_collect_to_vec(bytes),
+    }
+}
+
+/// Reads single pixels of a renderer back without waiting for the GPU: the
+/// result of a request shows up in a later frame.
+pub struct TexelProbe {
+    buffer: wgpu::Buffer,
+    /// The pixel being copied, while the buffer is in use.
+    pending: Option<[u32; 2]>,
+    /// Set by the callback of `map_async()`.
+    mapped: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+    result: Option<Texel>,
+}
+
+impl TexelProbe {
+    pub fn new(device: &wgpu::Device) -> TexelProbe {
+        TexelProbe {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("egui_plot_probe"),
+                size: PROBE_COLOR_OFFSET * 2,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            pending: None,
+            mapped: Arc::new(Mutex::new(None)),
+            result: None,
+        }
+    }
+
+    /// Starts copying pixel `[x, y]` of `texture` and `data_texture`, unless
+    /// a copy is already in flight.
+    pub fn request(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        data_texture: &wgpu::Texture,
+        pixel: [u32; 2],
+    ) {
+        let size = texture.size();
+        if self.pending.is_some() || pixel[0] >= size.width || pixel[1] >= size.height {
+            return;
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for (texture, offset) in [(data_texture, 0), (texture, PROBE_COLOR_OFFSET)] {
+            encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo {
+                    origin: wgpu::Origin3d {
+                        x: pixel[0],
+                        y: pixel[1],
+                        z: 0,
+                    },
+                    ..texture.as_image_copy()
+                },
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &self.buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset,
+                        bytes_per_row: None,
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d::default(),
+            );
+        }
+        queue.submit(core::iter::once(encoder.finish()));
+        let mapped = Arc::clone(&self.mapped);
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                *mapped.lock().unwrap() = Some(result);
+            });
+        self.pending = Some(pixel);
+    }
+
+    /// Picks up the copy in flight if it is done, and returns the last pixel
+    /// read. `texture` and `data_texture` give the formats.
+    pub fn poll(
+        &mut self,
+        device: &wgpu::Device,
+        texture: &wgpu::Texture,
+        data_texture: &wgpu::Texture,
+    ) -> Option<Texel> {
+        if let Some(pixel) = self.pending {
+            let _ = device.poll(wgpu::PollType::Poll);
+            let mapped = self.mapped.lock().unwrap().take();
+            if let Some(result) = mapped {
+                if result.is_ok() {
+                    let bytes = self.buffer.slice(..).get_mapped_range();
+                    let data_len = texel_size(data_texture.format()) as usize;
+                    let color_offset = PROBE_COLOR_OFFSET as usize;
+                    let data = data_texels(data_texture.format(), &bytes[..data_len]);
+                    let color =
+                        rgba_texels(texture.format(), &bytes[color_offset..color_offset + 4]);
+                    self.result = Some(Texel {
+                        pixel,
+                        rgba: color[0],
+                        data: PixelData::new(data[0]),
+                    });
+                }
+                self.buffer.unmap();
+                self.pending = None;
+            }
+        }
+        self.result
+    }
+
+    /// Whether a copy is in flight.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
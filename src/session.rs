@@ -0,0 +1,234 @@
+use crate::coloring::{Coloring, Filter, Interior, Palette, MAX_BOUNDARY};
+use crate::fractal::{Mode, MAX_ESCAPE_RADIUS, MAX_ITERATIONS};
+use crate::gradient::{palette_colors, GradientSpec, MAX_PALETTE_LEN, PALETTE_LEN};
+use crate::params::{finite, in_range};
+use crate::trap::Trap;
+use crate::view::View;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Key of the session in the eframe storage.
+pub const SESSION_KEY: &str = "session";
+
+/// Version of `Session` written by this build. Bump it when a change of the
+/// struct cannot be read by `serde` as is, and convert the older versions in
+/// `Session::load()`. Fields added later need `#[serde(default)]`.
+pub const SESSION_VERSION: u32 = 1;
+
+/// A palette as picked in the GUI: a gradient sampled into `len` entries,
+/// and how they are mapped, see `Palette`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaletteSettings {
+    pub gradient: GradientSpec,
+    pub len: usize,
+    pub offset: f32,
+    pub density: f32,
+    pub reverse: bool,
+    pub filter: Filter,
+}
+
+impl Default for PaletteSettings {
+    fn default() -> Self {
+        PaletteSettings {
+            gradient: GradientSpec::Preset("cubehelix_default".to_owned()),
+            len: PALETTE_LEN,
+            offset: 0.0,
+            density: 1.0,
+            reverse: false,
+            filter: Filter::Nearest,
+        }
+    }
+}
+
+impl PaletteSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        in_range("palette len", self.len, 1..=MAX_PALETTE_LEN)?;
+        finite("palette offset", self.offset)?;
+        finite("palette density", self.density)?;
+        self.gradient.gradient().map(|_| ())
+    }
+
+    pub fn palette(&self) -> Result<Palette, String> {
+        let gradient = self.gradient.gradient()?;
+        Ok(Palette {
+            colors: palette_colors(&*gradient, self.len.max(1)),
+            offset: self.offset,
+            density: self.density,
+            reverse: self.reverse,
+            filter: self.filter,
+        })
+    }
+}
+
+/// The user-visible state of the GUI, saved on exit and restored on start.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub mode: Mode,
+    pub views: HashMap<Mode, View>,
+    pub c: [f32; 2],
+    pub max_iterations: u32,
+    pub escape_radius: f32,
+    pub palette: PaletteSettings,
+    pub coloring: Coloring,
+    pub boundary: f32,
+    pub interior: Interior,
+    /// Name of the preset of the interior gradient.
+    pub interior_gradient: String,
+    pub trap: Trap,
+    pub show_trap: bool,
+    pub dual_view: bool,
+    pub show_orbit: bool,
+    pub show_cpu: bool,
+    pub show_gpu: bool,
+    pub use_series: bool,
+    #[serde(default)]
+    pub square_plot: bool,
+}
+
+/// Just the version of a saved `Session`, to pick how to read the rest.
+#[derive(Deserialize)]
+struct SessionVersion {
+    version: u32,
+}
+
+impl Session {
+    /// The saved session, if there is one this build can read.
+    pub fn load(storage: &dyn eframe::Storage) -> Option<Session> {
+        let version = eframe::get_value::<SessionVersion>(storage, SESSION_KEY)?.version;
+        let session: Option<Session> = match version {
+            SESSION_VERSION => eframe::get_value(storage, SESSION_KEY),
+            // Saved by a newer build.
+            _ => None,
+        };
+        let Some(session) = session else {
+            log::warn!("ignoring the saved session of version {version}");
+            return None;
+        };
+        if let Err(error) = session.validate() {
+            log::warn!("ignoring the saved session: {error}");
+            return None;
+        }
+        Some(session)
+    }
+
+    /// Checks the values like `Params::validate()`, as the storage can be
+    /// edited by hand or written by a build with other ranges.
+    pub fn validate(&self) -> Result<(), String> {
+        self.views.values().try_for_each(View::validate)?;
+        self.c.into_iter().try_for_each(|v| finite("c", v))?;
+        in_range("max_iterations", self.max_iterations, 1..=MAX_ITERATIONS)?;
+        in_range("escape_radius", self.escape_radius, 2.0..=MAX_ESCAPE_RADIUS)?;
+        self.palette.validate()?;
+        in_range("boundary", self.boundary, 0.0..=MAX_BOUNDARY)?;
+        self.trap.validate()
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SESSION_KEY, self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view::{MAX_ZOOM, MIN_ZOOM};
+    use egui_plot::PlotBounds;
+
+    fn session() -> Session {
+        Session {
+            version: SESSION_VERSION,
+            mode: Mode::Mandelbrot,
+            views: HashMap::from([(
+                Mode::Mandelbrot,
+                View::home(Mode::Mandelbrot.formula(), 1.5),
+            )]),
+            c: [-0.8, 0.156],
+            max_iterations: 1024,
+            escape_radius: 2.0,
+            palette: PaletteSettings::default(),
+            coloring: Coloring::Smooth,
+            boundary: 0.0,
+            interior: Interior::Black,
+            interior_gradient: "viridis".to_owned(),
+            trap: Trap::default(),
+            show_trap: false,
+            dual_view: false,
+            show_orbit: false,
+            show_cpu: false,
+            show_gpu: true,
+            use_series: true,
+            square_plot: false,
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        assert_eq!(session().validate(), Ok(()));
+        let invalid: [fn(&mut Session); 7] = [
+            |s| s.views.get_mut(&Mode::Mandelbrot).unwrap().zoom = 1e9,
+            |s| s.c[1] = f32::NAN,
+            |s| s.max_iterations = 0,
+            |s| s.escape_radius = f32::INFINITY,
+            |s| s.palette.len = 1 << 20,
+            |s| s.boundary = f32::NAN,
+            |s| s.trap.radius = -1.0,
+        ];
+        for (i, invalidate) in invalid.into_iter().enumerate() {
+            let mut session = session();
+            invalidate(&mut session);
+            assert!(session.validate().is_err(), "{i}");
+        }
+    }
+
+    /// Storage holding strings in memory.
+    #[derive(Default)]
+    struct Memory(HashMap<String, String>);
+
+    impl eframe::Storage for Memory {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_owned(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn navigation_stays_loadable() {
+        let dimensions = [300, 200];
+        let deep = |zoom| {
+            let mut view = View::home(Mode::Mandelbrot.formula(), 1.5);
+            view.zoom = zoom;
+            view
+        };
+        let mut zoomed = deep(MAX_ZOOM - 0.5);
+        zoomed.set_zoom(zoomed.zoom + 1.0);
+        let mut followed = deep(MAX_ZOOM - 0.5);
+        let bounds = PlotBounds::from_min_max([-0.375, -0.25], [0.375, 0.25]);
+        assert!(followed.follow_plot(&bounds, dimensions));
+        let mut unzoomed = deep(MIN_ZOOM + 0.5);
+        unzoomed.set_zoom(unzoomed.zoom - 1.0);
+        let views = [
+            zoomed,
+            followed,
+            deep(MAX_ZOOM - 0.5).zoom_box([-0.01; 2], [0.01; 2], dimensions, false),
+            unzoomed,
+            deep(MIN_ZOOM + 0.5).zoom_box([-0.01; 2], [0.01; 2], dimensions, true),
+        ];
+        for (i, view) in views.into_iter().enumerate() {
+            assert!((MIN_ZOOM..=MAX_ZOOM).contains(&view.zoom), "{i}");
+            let session = Session {
+                views: HashMap::from([(Mode::Mandelbrot, view)]),
+                ..session()
+            };
+            let mut storage = Memory::default();
+            session.save(&mut storage);
+            assert_eq!(Session::load(&storage), Some(session), "{i}");
+        }
+    }
+}
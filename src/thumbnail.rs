@@ -0,0 +1,105 @@
+use crate::fractal::{FractalRenderer, Mode, PerturbationFrame, Precision, Scene};
+use crate::perturbation::{ReferenceKey, References};
+use eframe::{egui, wgpu};
+use std::collections::HashMap;
+
+/// Size of the thumbnails, in pixels.
+pub const THUMBNAIL_SIZE: [u32; 2] = [128, 80];
+
+/// Renders small images of scenes into textures of their own, for the GUI.
+pub struct Thumbnailer {
+    target_format: wgpu::TextureFormat,
+    data_format: wgpu::TextureFormat,
+    /// One per mode, created on first use.
+    renderers: HashMap<Mode, FractalRenderer>,
+    references: References,
+}
+
+impl Thumbnailer {
+    pub fn new(
+        target_format: wgpu::TextureFormat,
+        data_format: wgpu::TextureFormat,
+    ) -> Thumbnailer {
+        Thumbnailer {
+            target_format,
+            data_format,
+            renderers: HashMap::new(),
+            references: References::default(),
+        }
+    }
+
+    /// Renders `scene` into a new texture of `THUMBNAIL_SIZE`, or returns
+    /// `None` while its reference orbit is computed in the background, after
+    /// which `ctx` is repainted.
+    pub fn render(
+        &mut self,
+        ctx: &egui::Context,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+    ) -> Option<wgpu::Texture> {
+        let renderer = self.renderers.entry(scene.mode).or_insert_with(|| {
+            FractalRenderer::for_scene(device, self.target_format, self.data_format, scene)
+        });
+        renderer.set_scene(scene);
+
+        let view = &scene.view;
+        let precision = Precision::for_view(view, THUMBNAIL_SIZE, renderer.supports_perturbation());
+        let perturbation = match precision {
+            Precision::Perturbation => {
+                let key = ReferenceKey {
+                    mode: scene.mode,
+                    point: view.center.clone(),
+                    c: scene.c,
+                    max_iterations: scene.max_iterations,
+                    bits: view.bits(THUMBNAIL_SIZE),
+                };
+                let pixel = view.pixel_size(THUMBNAIL_SIZE);
+                let reference = self.references.get(ctx, key, pixel, THUMBNAIL_SIZE)?;
+                Some(PerturbationFrame::new(
+                    reference,
+                    view,
+                    THUMBNAIL_SIZE,
+                    true,
+                ))
+            }
+            _ => None,
+        };
+        renderer.prepare(
+            device,
+            queue,
+            THUMBNAIL_SIZE,
+            view,
+            precision,
+            perturbation.as_ref(),
+        );
+        renderer.render(device, queue);
+
+        // The renderer draws every thumbnail into the same texture.
+        let [width, height] = THUMBNAIL_SIZE;
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("egui_plot_thumbnail"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.target_format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: Default::default(),
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_texture(
+            renderer.texture().as_image_copy(),
+            texture.as_image_copy(),
+            size,
+        );
+        queue.submit(core::iter::once(encoder.finish()));
+        Some(texture)
+    }
+}
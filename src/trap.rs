@@ -0,0 +1,146 @@
+use crate::params::{finite, in_range};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+/// Segments of the circle drawn by `Trap::outline()`.
+const CIRCLE_SEGMENTS: usize = 128;
+
+/// Largest `Trap::radius` offered.
+pub const MAX_TRAP_RADIUS: f32 = 4.0;
+
+/// The geometry of an orbit trap, see `trap_distance()` in
+/// `fractal_shader.wgsl`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TrapShape {
+    /// Distance to `center`.
+    Point = 0,
+    /// Distance to the line through `center` at `angle`.
+    Line = 1,
+    /// Distance to the nearer of the line through `center` at `angle` and its
+    /// perpendicular.
+    Cross = 2,
+    /// Distance to the circle of `radius` around `center`.
+    Circle = 3,
+    /// Pickover stalks: the cross, but only orbits passing within `radius`
+    /// of it are colored by the trap, the others keep their smooth
+    /// iteration count.
+    Stalks = 4,
+}
+
+impl TrapShape {
+    pub const ALL: [TrapShape; 5] = [
+        TrapShape::Point,
+        TrapShape::Line,
+        TrapShape::Cross,
+        TrapShape::Circle,
+        TrapShape::Stalks,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TrapShape::Point => "point",
+            TrapShape::Line => "line",
+            TrapShape::Cross => "cross",
+            TrapShape::Circle => "circle",
+            TrapShape::Stalks => "stalks",
+        }
+    }
+
+    /// Whether `Trap::angle` changes the shape.
+    pub fn uses_angle(self) -> bool {
+        matches!(self, TrapShape::Line | TrapShape::Cross | TrapShape::Stalks)
+    }
+
+    /// Whether `Trap::radius` changes the shape.
+    pub fn uses_radius(self) -> bool {
+        matches!(self, TrapShape::Circle | TrapShape::Stalks)
+    }
+}
+
+/// An orbit trap: `Coloring::Trap` colors each escaped point by how close
+/// its orbit came to the trap.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Trap {
+    pub shape: TrapShape,
+    pub center: [f32; 2],
+    /// Direction of the line, in radians from the real axis.
+    pub angle: f32,
+    /// Radius of the circle, or width of the stalks.
+    pub radius: f32,
+}
+
+impl Default for Trap {
+    fn default() -> Self {
+        Trap {
+            shape: TrapShape::Point,
+            center: [0.0, 0.0],
+            angle: 0.0,
+            radius: 0.1,
+        }
+    }
+}
+
+impl Trap {
+    pub fn validate(&self) -> Result<(), String> {
+        self.center
+            .into_iter()
+            .try_for_each(|v| finite("trap center", v))?;
+        finite("trap angle", self.angle)?;
+        in_range("trap radius", self.radius, 0.0..=MAX_TRAP_RADIUS)
+    }
+
+    /// Unit vector along the line.
+    pub fn direction(&self) -> [f32; 2] {
+        let (sin, cos) = self.angle.sin_cos();
+        [cos, sin]
+    }
+
+    /// Width of the stalks, 0 for the shapes that color every escaped point.
+    pub fn stalk_width(&self) -> f32 {
+        match self.shape {
+            TrapShape::Stalks => self.radius,
+            _ => 0.0,
+        }
+    }
+
+    /// The CPU counterpart of `trap_distance()`.
+    pub fn distance(&self, z: [f64; 2]) -> f64 {
+        let [x, y] = [0, 1].map(|i| z[i] - f64::from(self.center[i]));
+        let [cos, sin] = self.direction().map(f64::from);
+        let along = x * cos + y * sin;
+        let across = y * cos - x * sin;
+        match self.shape {
+            TrapShape::Point => x.hypot(y),
+            TrapShape::Line => across.abs(),
+            TrapShape::Cross | TrapShape::Stalks => along.abs().min(across.abs()),
+            TrapShape::Circle => (x.hypot(y) - f64::from(self.radius)).abs(),
+        }
+    }
+
+    /// Polylines tracing the trap in the plane, lines reaching `extent` away
+    /// from the center; a single point for `TrapShape::Point`.
+    pub fn outline(&self, extent: f64) -> Vec<Vec<[f64; 2]>> {
+        let [x, y] = self.center.map(f64::from);
+        let [cos, sin] = self.direction().map(f64::from);
+        let line = |[dx, dy]: [f64; 2]| {
+            vec![
+                [x - dx * extent, y - dy * extent],
+                [x + dx * extent, y + dy * extent],
+            ]
+        };
+        match self.shape {
+            TrapShape::Point => vec![vec![[x, y]]],
+            TrapShape::Line => vec![line([cos, sin])],
+            TrapShape::Cross | TrapShape::Stalks => vec![line([cos, sin]), line([-sin, cos])],
+            TrapShape::Circle => {
+                let radius = f64::from(self.radius);
+                vec![(0..=CIRCLE_SEGMENTS)
+                    .map(|i| {
+                        let (sin, cos) = (i as f64 / CIRCLE_SEGMENTS as f64 * TAU).sin_cos();
+                        [x + radius * cos, y + radius * sin]
+                    })
+                    .collect()]
+            }
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use crate::fractal::Formula;
+use crate::params::in_range;
+use crate::perturbation::{bits_for, Real, RealExp};
+use dashu_float::round::mode::HalfEven;
+use dashu_float::DBig;
+use egui_plot::PlotBounds;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Deviations of the plot bounds below this fraction of the view height are
+/// round-off from egui_plot, not navigation.
+const PLOT_TOLERANCE: f64 = 1e-9;
+
+/// Animations zooming by less than this many powers of 2 are pans.
+const ZOOM_TOLERANCE: f64 = 1e-9;
+
+/// Below this many f64 ulps of the center, axis labels switch to offsets
+/// from the center.
+const LABEL_ULPS: f64 = 1e3;
+
+/// The range of `View::zoom`. Centers take about `zoom` bits, so deeper views
+/// than `MAX_ZOOM` would take ages to compute references for, if they fit
+/// in memory at all.
+pub const MIN_ZOOM: f64 = -64.0;
+pub const MAX_ZOOM: f64 = 10_000.0;
+
+/// The part of the plane on screen: an arbitrary-precision center and a zoom
+/// exponent. Pixels are square, the aspect ratio comes from the image.
+///
+/// The plot does not hold the view, it reflects it: plot coordinates are
+/// offsets from `center` in units of the view height, so that egui_plot's f64
+/// bounds stay around `[-0.5, 0.5]` however deep the zoom.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(into = "ViewText", try_from = "ViewText")]
+pub struct View {
+    pub center: [Real; 2],
+    /// The view is `2^-zoom` units tall.
+    pub zoom: f64,
+}
+
+/// How a `View` is saved: the center in decimal, with all of its digits.
+#[derive(Serialize, Deserialize)]
+struct ViewText {
+    center: [String; 2],
+    zoom: f64,
+}
+
+impl From<View> for ViewText {
+    fn from(view: View) -> ViewText {
+        ViewText {
+            center: view.center_text(),
+            zoom: view.zoom,
+        }
+    }
+}
+
+impl TryFrom<ViewText> for View {
+    type Error = String;
+
+    fn try_from(text: ViewText) -> Result<View, String> {
+        let [re, im] = text
+            .center
+            .map(|v| parse_real(&v, 0).ok_or_else(|| format!("invalid coordinate: {v}")));
+        Ok(View {
+            center: [re?, im?],
+            zoom: text.zoom,
+        })
+    }
+}
+
+impl View {
+    /// The smallest view showing all of `formula.domain` with the aspect ratio
+    /// `aspect` (width over height).
+    pub fn home(formula: &Formula, aspect: f64) -> View {
+        let [[x_min, y_min], [x_max, y_max]] = formula.domain.map(|v| v.map(f64::from));
+        let height = (y_max - y_min).max((x_max - x_min) / aspect);
+        View {
+            center: [(x_min + x_max) / 2.0, (y_min + y_max) / 2.0].map(real),
+            zoom: -height.log2(),
+        }
+    }
+
+    /// Sets the zoom, clamped to `MIN_ZOOM..=MAX_ZOOM`. All navigation goes
+    /// through here, so that the views saved can be loaded again.
+    pub fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Checks that the zoom is within `MIN_ZOOM..=MAX_ZOOM`, for views that
+    /// come from outside.
+    pub fn validate(&self) -> Result<(), String> {
+        in_range("zoom", self.zoom, MIN_ZOOM..=MAX_ZOOM)
+    }
+
+    /// The center in decimal, with all of its digits.
+    pub fn center_text(&self) -> [String; 2] {
+        self.center.each_ref().map(|v| {
+            // Small values like 0 convert from f64 with few bits, and sums
+            // can carry a digit more than their precision.
+            let bits = v.precision().max(v.repr().digits());
+            format_real(v, bits.max(f64::MANTISSA_DIGITS as usize))
+        })
+    }
+
+    pub fn height(&self) -> RealExp {
+        RealExp::exp2(-self.zoom)
+    }
+
+    pub fn pixel_size(&self, dimensions: [u32; 2]) -> RealExp {
+        self.height()
+            .div(RealExp::new(f64::from(dimensions[1].max(1))))
+    }
+
+    /// Bits of precision of the center needed to tell pixels apart.
+    pub fn bits(&self, dimensions: [u32; 2]) -> usize {
+        bits_for(self.pixel_size(dimensions))
+    }
+
+    /// The point at `offset` from the center, in units of the view height.
+    pub fn point(&self, offset: [f64; 2], bits: usize) -> [Real; 2] {
+        let height = self.height();
+        [0, 1].map(|i| {
+            let offset = RealExp::new(offset[i]).mul(height);
+            (&self.center[i] + real_exp(offset, bits))
+                .with_precision(bits)
+                .value()
+        })
+    }
+
+    /// The view moved by `offset`, in plot coordinates.
+    pub fn pan(&self, offset: [f64; 2], dimensions: [u32; 2]) -> View {
+        View {
+            center: self.point(offset, self.bits(dimensions)),
+            zoom: self.zoom,
+        }
+    }
+
+    /// The smallest view showing all of the `[min, max]` rectangle of plot
+    /// coordinates or, if `out`, the view that this one fits in that
+    /// rectangle of.
+    pub fn zoom_box(&self, min: [f64; 2], max: [f64; 2], dimensions: [u32; 2], out: bool) -> View {
+        let center = [0, 1].map(|i| (min[i] + max[i]) / 2.0);
+        // The height of the rectangle, or more if it is wider than the view.
+        let scale = (max[1] - min[1]).max((max[0] - min[0]) / aspect(dimensions));
+        let (offset, zoom) = match out {
+            false => (center, self.zoom - scale.log2()),
+            true => (center.map(|v| -v / scale), self.zoom + scale.log2()),
+        };
+        let mut view = self.clone();
+        view.set_zoom(zoom);
+        view.center = self.point(offset, view.bits(dimensions));
+        view
+    }
+
+    /// The view of the `size` pixels at `origin`, from the top left, of an
+    /// image of `dimensions`. Its pixels are the same as in this view.
+    pub fn tile(&self, dimensions: [u32; 2], origin: [u32; 2], size: [u32; 2]) -> View {
+        let [width, height] = dimensions.map(f64::from);
+        let offset = [
+            (f64::from(origin[0]) + f64::from(size[0]) / 2.0 - width / 2.0) / height,
+            (height / 2.0 - f64::from(origin[1]) - f64::from(size[1]) / 2.0) / height,
+        ];
+        View {
+            center: self.point(offset, self.bits(dimensions)),
+            zoom: self.zoom + (height / f64::from(size[1])).log2(),
+        }
+    }
+
+    /// The view at `t` from 0 to 1 of a zoom from this view to `target`: the
+    /// zoom exponent moves linearly, about the one point that stays put on
+    /// the screen; a plain pan if the zoom is the same. `bits` is the
+    /// precision of the center.
+    pub fn zoom_towards(&self, target: &View, t: f64, bits: usize) -> View {
+        let zoom = self.zoom + (target.zoom - self.zoom) * t;
+        // `factor` is how much of the way from `target.center` to this center
+        // is left, going from 1 to 0 with the height of the view less the
+        // height of `target`. It is written in terms of the remaining zoom
+        // to stay accurate when deep.
+        let remaining = target.zoom - zoom;
+        let total = target.zoom - self.zoom;
+        let factor = if total.abs() < ZOOM_TOLERANCE {
+            RealExp::new(1.0 - t)
+        } else {
+            let shrink = |zoom: f64| -(-zoom * std::f64::consts::LN_2).exp_m1();
+            RealExp::exp2(self.zoom - zoom).mul(RealExp::new(shrink(remaining) / shrink(total)))
+        };
+        View {
+            center: [0, 1].map(|i| {
+                let offset = RealExp::from_real(&(&self.center[i] - &target.center[i])).mul(factor);
+                (&target.center[i] + real_exp(offset, bits))
+                    .with_precision(bits)
+                    .value()
+            }),
+            zoom,
+        }
+    }
+
+    /// Approximate f64 bounds, for the parts that are limited to f64 anyway.
+    pub fn bounds(&self, dimensions: [u32; 2]) -> PlotBounds {
+        let [x, y] = self.center.each_ref().map(|v| v.to_f64().value());
+        let height = self.height().to_f64();
+        let width = height * aspect(dimensions);
+        PlotBounds::from_min_max(
+            [x - width / 2.0, y - height / 2.0],
+            [x + width / 2.0, y + height / 2.0],
+        )
+    }
+
+    /// Where `other` appears in the plot of this view: its center and its
+    /// height in plot coordinates.
+    pub fn locate(&self, other: &View) -> ([f64; 2], f64) {
+        let height = self.height();
+        let center = [0, 1].map(|i| {
+            RealExp::from_real(&(&other.center[i] - &self.center[i]))
+                .div(height)
+                .to_f64()
+        });
+        (center, (self.zoom - other.zoom).exp2())
+    }
+
+    /// Where the point `point` of the plane appears in the plot, for overlays
+    /// that do not need more than f64.
+    pub fn plot_point(&self, point: [f64; 2]) -> [f64; 2] {
+        let height = self.height();
+        [0, 1].map(|i| {
+            let offset = real(point[i]) - &self.center[i];
+            RealExp::from_real(&offset).div(height).to_f64()
+        })
+    }
+
+    /// The plot bounds reflecting this view in an image of `dimensions`.
+    pub fn plot_bounds(&self, dimensions: [u32; 2]) -> PlotBounds {
+        let half = [aspect(dimensions) / 2.0, 0.5];
+        PlotBounds::from_min_max([-half[0], -half[1]], half)
+    }
+
+    /// Applies the navigation done in the plot since it was last set to
+    /// `plot_bounds()`, returning whether the view changed.
+    pub fn follow_plot(&mut self, bounds: &PlotBounds, dimensions: [u32; 2]) -> bool {
+        if !bounds.is_valid() || bounds.height() <= 0.0 {
+            return false;
+        }
+        let center = bounds.center();
+        let zoom = -bounds.height().log2();
+        if center.x.abs().max(center.y.abs()).max(zoom.abs()) < PLOT_TOLERANCE {
+            return false;
+        }
+        let bits = bits_for(
+            self.pixel_size(dimensions)
+                .mul(RealExp::new(bounds.height())),
+        );
+        self.center = self.point([center.x, center.y], bits);
+        self.set_zoom(self.zoom + zoom);
+        true
+    }
+
+    /// Formats the coordinate `axis` of the point at `offset` plot units from
+    /// the center, with `resolution` plot units.
+    pub fn label(&self, axis: usize, offset: f64, resolution: f64) -> String {
+        let height = self.height();
+        let resolution = RealExp::new(resolution).mul(height);
+        let center = self.center[axis].to_f64().value();
+        if resolution.log2() < (LABEL_ULPS * f64::EPSILON * center.abs().max(1.0)).log2() {
+            // f64 cannot tell the labels apart anymore.
+            return format_scientific(RealExp::new(offset).mul(height));
+        }
+        let digits = (-resolution.log2() * std::f64::consts::LOG10_2)
+            .ceil()
+            .max(0.0) as usize;
+        format!("{:.*}", digits, center + offset * height.to_f64())
+    }
+}
+
+/// The pixel, from the top left, under `point` of the plot of an image of
+/// `dimensions`, if it is inside.
+pub fn plot_pixel(point: [f64; 2], dimensions: [u32; 2]) -> Option<[u32; 2]> {
+    let height = f64::from(dimensions[1]);
+    let pixel = [
+        (point[0] + aspect(dimensions) / 2.0) * height,
+        (0.5 - point[1]) * height,
+    ];
+    let inside = (0..2).all(|i| pixel[i] >= 0.0 && pixel[i] < f64::from(dimensions[i]));
+    inside.then(|| pixel.map(|v| v as u32))
+}
+
+fn aspect(dimensions: [u32; 2]) -> f64 {
+    f64::from(dimensions[0].max(1)) / f64::from(dimensions[1].max(1))
+}
+
+/// Formats `v` in signed scientific notation, whatever its exponent.
+fn format_scientific(v: RealExp) -> String {
+    if v.mantissa == 0.0 {
+        return "0".to_string();
+    }
+    let log10 = v.log2() * std::f64::consts::LOG10_2;
+    let exponent = log10.floor();
+    let mantissa = v.mantissa.signum() * 10f64.powf(log10 - exponent);
+    format!("{mantissa:+.3}e{exponent}")
+}
+
+fn real(v: f64) -> Real {
+    Real::try_from(v).unwrap()
+}
+
+fn real_exp(v: RealExp, bits: usize) -> Real {
+    (real(v.mantissa).with_precision(bits).value() << v.exponent as isize)
+        .with_precision(bits)
+        .value()
+}
+
+/// Formats `v` in decimal with enough digits for `bits` bits: parsing the
+/// text back and rounding it to `bits` bits gives `v` rounded to `bits` bits.
+pub fn format_real(v: &Real, bits: usize) -> String {
+    let bits = bits.max(1);
+    let digits = (bits as f64 * std::f64::consts::LOG10_2).ceil() as usize + 1;
+    v.clone()
+        .with_precision(bits)
+        .value()
+        .with_base_and_precision::<10>(digits)
+        .value()
+        .to_string()
+}
+
+/// Parses a decimal number, keeping all of its digits and at least `bits`
+/// bits.
+pub fn parse_real(s: &str, bits: usize) -> Option<Real> {
+    let v = DBig::from_str(s.trim()).ok()?;
+    let bits = bits.max((v.precision() as f64 / std::f64::consts::LOG10_2).ceil() as usize);
+    Some(
+        v.with_base_and_precision::<2>(bits)
+            .value()
+            .with_rounding::<HalfEven>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMENSIONS: [u32; 2] = [300, 200];
+
+    fn view(center: [&str; 2], zoom: f64) -> View {
+        View {
+            center: center.map(|v| parse_real(v, 0).unwrap()),
+            zoom,
+        }
+    }
+
+    /// Whether `a` and `b` show the same part of the plane, to a small
+    /// fraction of a pixel of `b`.
+    fn same_view(a: &View, b: &View) -> bool {
+        let (center, height) = b.locate(a);
+        center.iter().all(|v| v.abs() < 1e-6 / 200.0) && (height - 1.0).abs() < 1e-9
+    }
+
+    #[test]
+    fn decimal_round_trip() {
+        let digits = "-0.7436438870371587047521915061147746206327418236845671234567890123456789";
+        for bits in [24, 53, 64, 128, 300, 1000] {
+            let v = parse_real(digits, 0).unwrap().with_precision(bits).value();
+            let text = format_real(&v, bits);
+            let parsed = parse_real(&text, bits)
+                .unwrap()
+                .with_precision(bits)
+                .value();
+            assert_eq!(parsed, v, "{bits} bits: {text}");
+        }
+    }
+
+    #[test]
+    fn view_round_trip() {
+        for zoom in [-1.5, 40.0, 400.0] {
+            let mut view = view(["-0.75", "0.1"], zoom);
+            let bits = view.bits(DIMENSIONS);
+            view.center = view.point([0.123456789, -0.3], bits);
+            let text = serde_json::to_string(&view).unwrap();
+            let parsed: View = serde_json::from_str(&text).unwrap();
+            assert_eq!(parsed.zoom, view.zoom);
+            for (parsed, center) in parsed.center.iter().zip(&view.center) {
+                let bits = center.precision().max(center.repr().digits());
+                let parsed = parsed.clone().with_precision(bits).value();
+                assert_eq!(&parsed, center, "{text}");
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_stitch_into_the_view() {
+        for zoom in [-1.0, 80.0] {
+            let parent = view(["-0.75", "0.1"], zoom);
+            let tile_size = [128, 96];
+            for origin in [[0, 0], [128, 0], [256, 96], [0, 192]] {
+                let size = [
+                    tile_size[0].min(DIMENSIONS[0] - origin[0]),
+                    tile_size[1].min(DIMENSIONS[1] - origin[1]),
+                ];
+                let tile = parent.tile(DIMENSIONS, origin, size);
+                let pixel_ratio = tile.pixel_size(size).div(parent.pixel_size(DIMENSIONS));
+                assert!((pixel_ratio.to_f64() - 1.0).abs() < 1e-12);
+                // The center of the tile, in plot units of the parent.
+                let height = f64::from(DIMENSIONS[1]);
+                let expected = [
+                    (f64::from(origin[0]) + f64::from(size[0]) / 2.0) / height
+                        - aspect(DIMENSIONS) / 2.0,
+                    0.5 - (f64::from(origin[1]) + f64::from(size[1]) / 2.0) / height,
+                ];
+                let (center, _) = parent.locate(&tile);
+                for i in 0..2 {
+                    assert!((center[i] - expected[i]).abs() < 1e-6 / height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zoom_towards_endpoints() {
+        for (zoom, target_zoom) in [(-1.0, 20.0), (100.0, 300.0), (50.0, 50.0), (30.0, 2.0)] {
+            let start = view(["-0.75", "0.1"], zoom);
+            let mut target = view(["-0.75", "0.1"], target_zoom);
+            let bits = target.bits(DIMENSIONS).max(start.bits(DIMENSIONS));
+            target.center = start.point([0.3, -0.2], bits);
+            assert!(same_view(&start.zoom_towards(&target, 0.0, bits), &start));
+            assert!(same_view(&start.zoom_towards(&target, 1.0, bits), &target));
+        }
+    }
+
+    #[test]
+    fn zoom_box_and_pan() {
+        let view = view(["-0.75", "0.1"], 60.0);
+        let zoomed = view.zoom_box([-0.125, -0.25], [0.625, 0.25], DIMENSIONS, false);
+        assert_eq!(zoomed.zoom, view.zoom + 1.0);
+        let (center, _) = view.locate(&zoomed);
+        assert!((center[0] - 0.25).abs() < 1e-9 && center[1].abs() < 1e-9);
+        // Zooming out of the same box goes back.
+        let back = zoomed.zoom_box([-0.125, -0.25], [0.625, 0.25], DIMENSIONS, true);
+        assert!(same_view(&back, &view));
+        let panned = view.pan([0.5, -0.25], DIMENSIONS);
+        let (center, _) = view.locate(&panned);
+        assert!((center[0] - 0.5).abs() < 1e-9 && (center[1] + 0.25).abs() < 1e-9);
+    }
+}
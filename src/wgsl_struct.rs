@@ -1,22 +1,98 @@
 #[repr(C)]
-#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: [f32; 2],
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, /*Default,*/ bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, /*Default,*/ PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct UniformParams {
-    // 0    8
-    pub x_bounds: [f32; 2],
-    // 8    8
-    pub y_bounds: [f32; 2],
-    // 16   4
+    // 0    8   (hi, lo) of the left edge of the view
+    pub x_min: [f32; 2],
+    // 8    8   (hi, lo) of the bottom edge of the view
+    pub y_min: [f32; 2],
+    // 16   8   width and height of the view
+    pub extent: [f32; 2],
+    // 24   8   width and height of one pixel
+    pub pixel_size: [f32; 2],
+    // 32   4
     pub max_iterations: u32,
-    // 20
-    pub padding0: u32,
-    // 24   8
+    // 36   4   `Precision` as u32
+    pub precision: u32,
+    // 40   8
     pub c: [f32; 2],
+    // 48   4
+    pub escape_radius: f32,
+    // 52   4   `TrapShape` as u32
+    pub trap_shape: u32,
+    // 56   8
+    pub trap_center: [f32; 2],
+    // 64   8   unit vector along the line of the trap
+    pub trap_direction: [f32; 2],
+    // 72   4
+    pub trap_radius: f32,
+    // 76   4
+    pub padding0: u32,
+}
+
+/// Parameters of `coloring.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColoringParams {
+    // 0    4   `Coloring` as u32
+    pub coloring: u32,
+    // 4    4   number of colors in the palette texture
+    pub palette_len: u32,
+    // 8    4   `Filter` as u32
+    pub palette_filter: u32,
+    // 12   4
+    pub reverse: u32,
+    // 16   4   fraction of the palette length
+    pub offset: f32,
+    // 20   4   palette entries per iteration
+    pub density: f32,
+    // 24   4   width of the darkened boundary in pixels, 0 for none
+    pub boundary: f32,
+    // 28   4   width of the Pickover stalks, 0 for other traps
+    pub stalk_width: f32,
+    // 32   4   `Interior` as u32
+    pub interior: u32,
+    // 36   4
+    pub padding0: u32,
+    // 40   4
+    pub padding1: u32,
+    // 44   4
+    pub padding2: u32,
+}
+
+/// Per-view parameters of `perturbation.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PerturbationParams {
+    // 0    16  series coefficients A, B, C as (re, im, exponent, 0)
+    pub series_a: [f32; 4],
+    // 16   16
+    pub series_b: [f32; 4],
     // 32   16
-    pub palette: [[f32; 4]; crate::COLOR_NUM],
-}
\ No newline at end of file
+    pub series_c: [f32; 4],
+    // 48   8   position of the reference point in pixels
+    pub reference_pixel: [f32; 2],
+    // 56   8   mantissas of the pixel width and height
+    pub pixel_scale: [f32; 2],
+    // 64   4   exponent shared by `pixel_scale`
+    pub pixel_exponent: f32,
+    // 68   4   iterations skipped by the series approximation
+    pub skip: u32,
+    // 72   4
+    pub primary_len: u32,
+    // 76   4
+    pub critical_offset: u32,
+    // 80   4
+    pub critical_len: u32,
+    // 84   4   `Plane` as u32
+    pub plane: u32,
+    // 88   4
+    pub padding0: u32,
+    // 92   4
+    pub padding1: u32,
+}